@@ -49,8 +49,10 @@ pub fn connect_rs_impl(args: TokenStream, input: TokenStream) -> TokenStream {
 
     // Extract method names and signatures
     let mut method_impls = Vec::new();
+    let mut wrapped_methods = Vec::new();
     let mut route_handlers = Vec::new();
     let mut route_registrations = Vec::new();
+    let mut serde_registrations = Vec::new();
 
     for item in items {
         if let syn::ImplItem::Fn(method) = item {
@@ -68,10 +70,10 @@ pub fn connect_rs_impl(args: TokenStream, input: TokenStream) -> TokenStream {
             let inputs = &method.sig.inputs;
             let output = &method.sig.output;
 
-            // Get the request type (second parameter)
-            let request_type = if inputs.len() >= 2 {
+            // Get the request pattern and type (second parameter)
+            let (request_pat, request_type) = if inputs.len() >= 2 {
                 if let syn::FnArg::Typed(pat_type) = &inputs[1] {
-                    &pat_type.ty
+                    (&pat_type.pat, &pat_type.ty)
                 } else {
                     abort!(inputs, "Expected typed parameter for request");
                 }
@@ -121,12 +123,59 @@ pub fn connect_rs_impl(args: TokenStream, input: TokenStream) -> TokenStream {
 
             let method_block = &method.block;
 
-            // Generate the trait implementation for this method
-            method_impls.push(quote! {
-                async fn #method_name(&self, request: #request_type)
-                    -> Result<#response_type, connect_axum::ConnectError>
-                #method_block
-            });
+            // Methods may take a Request<RequestType> and/or return a Response<ResponseType>
+            // to access the headers and extensions of the request and response
+            let request_wrapper = wrapped_type(request_type, "Request");
+            let response_wrapper = wrapped_type(response_type, "Response");
+            let request_type = request_wrapper.unwrap_or(request_type);
+            let response_type = response_wrapper.unwrap_or(response_type);
+
+            if request_wrapper.is_none() && response_wrapper.is_none() {
+                // Generate the trait implementation for this method
+                method_impls.push(quote! {
+                    async fn #method_name(&self, #request_pat: #request_type)
+                        -> Result<#response_type, connect_axum::ConnectError>
+                    #method_block
+                });
+            } else {
+                // Keep the method as written, and implement the trait method on top of it
+                let wrap_request = if request_wrapper.is_some() {
+                    quote! { connect_axum::Request::new(request) }
+                } else {
+                    quote! { request }
+                };
+
+                let unwrap_response = if response_wrapper.is_some() {
+                    quote! { .map(connect_axum::Response::into_message) }
+                } else {
+                    quote! {}
+                };
+
+                wrapped_methods.push(method);
+
+                method_impls.push(quote! {
+                    async fn #method_name(&self, request: #request_type)
+                        -> Result<#response_type, connect_axum::ConnectError>
+                    {
+                        // Inherent methods take precedence, so this calls the method as written
+                        Self::#method_name(self, #wrap_request).await #unwrap_response
+                    }
+                });
+            }
+
+            // Handlers always pass a Request and get back a Response, unwrapping and wrapping
+            // the messages for methods that don't use them
+            let request_arg = if request_wrapper.is_some() {
+                quote! { request }
+            } else {
+                quote! { request.into_message() }
+            };
+
+            let into_response = if response_wrapper.is_some() {
+                quote! {}
+            } else {
+                quote! { .map(connect_axum::Response::new) }
+            };
 
             // Generate the route handler
             let handler_name = syn::Ident::new(
@@ -134,41 +183,201 @@ pub fn connect_rs_impl(args: TokenStream, input: TokenStream) -> TokenStream {
                 method_name.span(),
             );
 
+            // Client-streaming methods take RequestStream<RequestType>, server-streaming methods
+            // return Result<ResponseStream<ResponseType>, ConnectError>, bidi methods do both
+            let request_item_type = wrapped_type(request_type, "RequestStream");
+            let response_item_type = wrapped_type(response_type, "ResponseStream");
+
+            let call_name =
+                syn::Ident::new(&format!("__connect_call_{method_name}"), method_name.span());
+
+            let read_request = match request_item_type {
+                Some(item_type) if response_item_type.is_some() => quote! {
+                    let request = connect_axum::RequestStream::<#item_type>::decode(
+                        connect_req.body,
+                        connect_req.encoding.clone(),
+                        connect_req.request_compression,
+                        connect_req.limits,
+                    );
+
+                    // HTTP/1.1 can't reliably interleave the request and response bodies,
+                    // so bidi streams are half-duplex there: the whole request is read first
+                    let request = if connect_req.version < axum::http::Version::HTTP_2 {
+                        request.half_duplex().await
+                    } else {
+                        request
+                    };
+                },
+                Some(item_type) => quote! {
+                    let request = connect_axum::RequestStream::<#item_type>::decode(
+                        connect_req.body,
+                        connect_req.encoding.clone(),
+                        connect_req.request_compression,
+                        connect_req.limits,
+                    );
+                },
+                None => quote! {
+                    let request = connect_axum::RequestStream::<#request_type>::decode(
+                        connect_req.body,
+                        connect_req.encoding.clone(),
+                        connect_req.request_compression,
+                        connect_req.limits,
+                    )
+                    .into_message()
+                    .await?;
+                },
+            };
+
+            let call_service = if response_item_type.is_some() {
+                quote! { service.#method_name(#request_arg).await #into_response }
+            } else {
+                quote! {
+                    service
+                        .#method_name(#request_arg)
+                        .await
+                        #into_response
+                        .map(|response| response.map(connect_axum::ResponseStream::from_message))
+                }
+            };
+
+            let request_message_type = request_item_type.unwrap_or(request_type);
+            let response_message_type = response_item_type.unwrap_or(response_type);
+
+            // The messages' serde impls, if they have some, for codecs like JSON
+            serde_registrations.push(quote! {
+                .with_serde_impls(
+                    #meta_path::#method_const_ident.path,
+                    connect_axum::serde_impls!(#request_message_type),
+                    connect_axum::serde_impls!(#response_message_type),
+                )
+            });
+
+            // Every method can be called as a stream of enveloped messages: that's how Connect
+            // streaming methods and all gRPC methods are called
+            route_handlers.push(quote! {
+                #[allow(deprecated)]
+                async fn #call_name(
+                    service: std::sync::Arc<#self_ty>,
+                    connect_req: connect_axum::ConnectStreamRequest,
+                ) -> connect_axum::Response<connect_axum::ResponseStream<#response_message_type>> {
+                    let deadline = connect_axum::deadline::from_timeout_ms(connect_req.timeout_ms);
+
+                    // Decode the request message(s) and call the service method, within the
+                    // client's deadline (which also bounds the response stream). From here on,
+                    // errors are reported at the end of the stream rather than as HTTP errors.
+                    connect_axum::deadline::run(deadline, async {
+                        #read_request
+
+                        let request = connect_axum::Request::from_parts(
+                            connect_req.headers,
+                            connect_req.extensions,
+                            request,
+                        );
+
+                        #call_service
+                    })
+                    .await
+                    .unwrap_or_else(|e| {
+                        connect_axum::Response::new(connect_axum::ResponseStream::from_error(e))
+                    })
+                    .map(|response_stream| response_stream.with_deadline(deadline))
+                }
+            });
+
+            // gRPC requests are told apart from Connect ones by their content type
+            let serve_grpc = quote! {
+                if connect_axum::grpc::is_grpc_request(&req) {
+                    return Ok(connect_axum::grpc::handle(req, &options, |grpc_req| #call_name(service, grpc_req)).await);
+                }
+            };
+
+            if request_item_type.is_some() || response_item_type.is_some() {
+                route_handlers.push(quote! {
+                    #[allow(deprecated)]
+                    async fn #handler_name(
+                        axum::extract::State((service, options)): axum::extract::State<(
+                        std::sync::Arc<#self_ty>,
+                        std::sync::Arc<connect_axum::RouterOptions>,
+                    )>,
+                        req: axum::extract::Request,
+                    ) -> Result<axum::response::Response, connect_axum::ProtocolError> {
+                        #serve_grpc
+
+                        // Parse the headers of the incoming Connect streaming request
+                        let connect_req = connect_axum::parse_connect_stream_request(req, &options)?;
+                        let encoding = connect_req.encoding.clone();
+                        let response_compression = connect_req.response_compression;
+                        let limits = connect_req.limits;
+
+                        let response = #call_name(service, connect_req).await;
+
+                        // The streaming HTTP response, made of enveloped messages
+                        Ok(connect_axum::encode_stream_response(
+                            response,
+                            encoding,
+                            response_compression,
+                            limits,
+                        )?)
+                    }
+                });
+
+                // Streaming methods are always POST
+                route_registrations.push(quote! {
+                    .route(
+                        #meta_path::#method_const_ident.path,
+                        axum::routing::post(#handler_name)
+                    )
+                });
+
+                continue;
+            }
+
             route_handlers.push(quote! {
+                #[allow(deprecated)]
                 async fn #handler_name(
-                    axum::extract::State(service): axum::extract::State<std::sync::Arc<#self_ty>>,
+                    axum::extract::State((service, options)): axum::extract::State<(
+                        std::sync::Arc<#self_ty>,
+                        std::sync::Arc<connect_axum::RouterOptions>,
+                    )>,
                     req: axum::extract::Request,
-                ) -> Result<axum::response::Response, connect_axum::ConnectError> {
-                    use connect_axum::{ConnectMessageJson, ConnectMessageProto};
+                ) -> Result<axum::response::Response, connect_axum::ProtocolError> {
+                    #serve_grpc
 
                     // Parse the incoming Connect request
-                    let connect_req = connect_axum::parse_connect_request(req).await?;
+                    let connect_req = connect_axum::parse_connect_request(req, &options).await?;
+                    let deadline = connect_axum::deadline::from_timeout_ms(connect_req.timeout_ms);
 
                     // Decode the request message
-                    let request_msg = match connect_req.encoding {
-                        connect_axum::Encoding::Json => {
-                            <#request_type as ConnectMessageJson>::decode_json(&connect_req.message)?
-                        }
-                        connect_axum::Encoding::Proto => {
-                            <#request_type as ConnectMessageProto>::decode_proto(&connect_req.message)?
-                        }
-                    };
+                    let request_msg = connect_req
+                        .encoding
+                        .decode_request::<#request_type>(&connect_req.message)?;
 
-                    // Call the service method
-                    let response_msg = service.#method_name(request_msg).await?;
+                    let request = connect_axum::Request::from_parts(
+                        connect_req.headers,
+                        connect_req.extensions,
+                        request_msg,
+                    );
+
+                    // Call the service method, cancelling it if the client's deadline passes
+                    let response = connect_axum::deadline::run(deadline, async {
+                        service.#method_name(#request_arg).await #into_response
+                    })
+                    .await?;
 
                     // Encode the response
-                    let response_bytes = match connect_req.encoding {
-                        connect_axum::Encoding::Json => {
-                            <#response_type as ConnectMessageJson>::encode_json(&response_msg)?
-                        }
-                        connect_axum::Encoding::Proto => {
-                            <#response_type as ConnectMessageProto>::encode_proto(&response_msg)?
-                        }
-                    };
+                    let response = response.try_map(|response_msg| {
+                        connect_req
+                            .encoding
+                            .encode_response::<#response_type>(&response_msg)
+                    })?;
 
                     // The final HTTP response
-                    connect_axum::encode_http_response(response_bytes, connect_req.encoding)
+                    Ok(connect_axum::encode_http_response(
+                        response,
+                        connect_req.encoding,
+                        connect_req.response_compression,
+                        connect_req.limits,
+                    )?)
                 }
             });
 
@@ -189,25 +398,70 @@ pub fn connect_rs_impl(args: TokenStream, input: TokenStream) -> TokenStream {
         }
     }
 
+    // Deprecated services and methods are for their callers to avoid, not for their
+    // implementations to warn about
     let expanded = quote! {
+        #[allow(deprecated)]
         impl #impl_generics #trait_path for #self_ty {
             #(#method_impls)*
         }
 
         impl #impl_generics #self_ty {
+            #(#wrapped_methods)*
+
+            /// The router serving the service, with the default options (which need none of the
+            /// embedded descriptors)
             pub fn into_router(self) -> axum::Router {
+                self.__connect_router(connect_axum::RouterOptions::default())
+            }
+
+            /// The router serving the service with `options`. Fails if the descriptors embedded by
+            /// the code generator don't decode, for codecs that need them (like canonical JSON).
+            pub fn into_router_with(
+                self,
+                options: connect_axum::RouterOptions,
+            ) -> Result<axum::Router, connect_axum::ConnectError> {
+                let options = options.with_file_descriptor_set(#meta_path::FILE_DESCRIPTOR_SET)?;
+
+                Ok(self.__connect_router(options))
+            }
+
+            fn __connect_router(self, options: connect_axum::RouterOptions) -> axum::Router {
                 use axum::routing::{post, get};
 
                 let service = std::sync::Arc::new(self);
+                let options = std::sync::Arc::new(options #(#serde_registrations)*);
 
                 #(#route_handlers)*
 
                 axum::Router::new()
                     #(#route_registrations)*
-                    .with_state(service)
+                    .with_state((service, options))
             }
         }
     };
 
     TokenStream::from(expanded)
 }
+
+/// If `ty` is `Wrapper<Inner>` (matching on the last path segment), returns `Inner`
+fn wrapped_type<'a>(ty: &'a syn::Type, wrapper: &str) -> Option<&'a syn::Type> {
+    let syn::Type::Path(type_path) = ty else {
+        return None;
+    };
+
+    let segment = type_path.path.segments.last()?;
+
+    if segment.ident != wrapper {
+        return None;
+    }
+
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    match args.args.first() {
+        Some(syn::GenericArgument::Type(inner)) => Some(inner),
+        _ => None,
+    }
+}
@@ -0,0 +1,345 @@
+//! Calls made by the generated clients, over any [`Transport`].
+//!
+//! https://connectrpc.com/docs/protocol/#unary-request
+use std::time::{Duration, Instant};
+
+use crate::deadline::MAX_TIMEOUT_DIGITS;
+use crate::encode::{ErrorBody, UNARY_TRAILER_PREFIX};
+use crate::envelope::Envelope;
+use crate::stream::{Stream, encode_messages};
+use crate::transport::Transport;
+use crate::{
+    CONNECT_PROTOCOL_VERSION, CONNECT_TIMEOUT_MS, Code, Compression, ConnectError,
+    ConnectMessageProto, Encoding, PROTOCOL_VERSION, Response, ResponseStream,
+};
+use axum::body::Body;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use http::header::{CONTENT_ENCODING, CONTENT_TYPE, IntoHeaderName};
+use http::{Extensions, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use http_body_util::BodyExt;
+use url::form_urlencoded;
+
+/// Options for a single call, passed to the `*_with` variant of a generated client's method
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    timeout: Option<Duration>,
+    headers: HeaderMap,
+}
+
+impl CallOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail the call with `deadline_exceeded` if it takes longer than `timeout`. The server is
+    /// told too (with `Connect-Timeout-Ms`), so it can give up on the call as well.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send a header with the request, like `options.header("token", HeaderValue::from_static("..."))`
+    pub fn header(mut self, name: impl IntoHeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// The deadline of a call starting now
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// The headers to send with the request: the added ones, the protocol version and the timeout
+    fn request_headers(&self) -> HeaderMap {
+        let mut headers = self.headers.clone();
+
+        headers.insert(
+            HeaderName::from_static(CONNECT_PROTOCOL_VERSION),
+            HeaderValue::from_static(PROTOCOL_VERSION),
+        );
+
+        if let Some(timeout) = self.timeout {
+            let max_timeout_ms = 10u128.pow(MAX_TIMEOUT_DIGITS as u32) - 1;
+            let timeout_ms = timeout.as_millis().clamp(1, max_timeout_ms) as u64;
+
+            headers.insert(
+                HeaderName::from_static(CONNECT_TIMEOUT_MS),
+                timeout_ms.into(),
+            );
+        }
+
+        headers
+    }
+}
+
+/// Call a unary method at `url`, with a `GET` if `get` is set (only allowed for methods without
+/// side effects)
+pub async fn unary<T, Req, Res>(
+    transport: &T,
+    url: &str,
+    encoding: &Encoding,
+    get: bool,
+    request: Req,
+    options: CallOptions,
+) -> Result<Response<Res>, ConnectError>
+where
+    T: Transport,
+    Req: ConnectMessageProto,
+    Res: ConnectMessageProto,
+{
+    crate::deadline::run(options.deadline(), async {
+        let message = encoding.encode_request(&request)?;
+
+        let request = if get {
+            let url = format!("{url}?{}", get_query(encoding, &message));
+            http_request(Method::GET, &url, None, Body::empty(), &options)?
+        } else {
+            let content_type = crate::encode::content_type("", encoding);
+            http_request(
+                Method::POST,
+                url,
+                Some(content_type),
+                message.into(),
+                &options,
+            )?
+        };
+
+        let (parts, body) = transport.send(request).await?.into_parts();
+
+        let body = body
+            .collect()
+            .await
+            .map_err(|e| ConnectError::internal(format!("Failed to read response: {e}")))?
+            .to_bytes();
+
+        if !parts.status.is_success() {
+            return Err(error_from_response(parts.status, parts.headers, &body));
+        }
+
+        unary_response(encoding, parts.headers, &body)
+    })
+    .await
+}
+
+/// Call a server-streaming method at `url`
+pub async fn server_streaming<T, Req, Res>(
+    transport: &T,
+    url: &str,
+    encoding: &Encoding,
+    request: Req,
+    options: CallOptions,
+) -> Result<Response<ResponseStream<Res>>, ConnectError>
+where
+    T: Transport,
+    Req: ConnectMessageProto,
+    Res: ConnectMessageProto,
+{
+    let body = Envelope::message(encoding.encode_request(&request)?).encode()?;
+
+    stream_call(transport, url, encoding, body.into(), options).await
+}
+
+/// Call a client-streaming or bidirectional method at `url`, sending `requests` as they come
+pub async fn streaming<T, Req, Res>(
+    transport: &T,
+    url: &str,
+    encoding: &Encoding,
+    requests: impl Stream<Item = Req> + Send + 'static,
+    options: CallOptions,
+) -> Result<Response<ResponseStream<Res>>, ConnectError>
+where
+    T: Transport,
+    Req: ConnectMessageProto,
+    Res: ConnectMessageProto,
+{
+    let body = Body::from_stream(encode_messages(requests, encoding.clone()));
+
+    stream_call(transport, url, encoding, body, options).await
+}
+
+/// The one and only message of a streaming response, along with the stream's trailers
+pub async fn single_response<T>(
+    response: Response<ResponseStream<T>>,
+) -> Result<Response<T>, ConnectError> {
+    let Response {
+        message: mut messages,
+        headers,
+        extensions,
+        ..
+    } = response;
+
+    let message = crate::stream::single_message(&mut messages, Code::Unimplemented).await?;
+    let trailers = messages.trailers().cloned().unwrap_or_default();
+
+    Ok(Response {
+        message,
+        headers,
+        trailers,
+        extensions,
+    })
+}
+
+async fn stream_call<T, Res>(
+    transport: &T,
+    url: &str,
+    encoding: &Encoding,
+    body: Body,
+    options: CallOptions,
+) -> Result<Response<ResponseStream<Res>>, ConnectError>
+where
+    T: Transport,
+    Res: ConnectMessageProto,
+{
+    let deadline = options.deadline();
+
+    crate::deadline::run(deadline, async {
+        let content_type = crate::encode::content_type("connect+", encoding);
+        let request = http_request(Method::POST, url, Some(content_type), body, &options)?;
+
+        let (parts, body) = transport.send(request).await?.into_parts();
+
+        if !parts.status.is_success() {
+            let body = body.collect().await.map(|body| body.to_bytes());
+            return Err(error_from_response(
+                parts.status,
+                parts.headers,
+                &body.unwrap_or_default(),
+            ));
+        }
+
+        let messages = ResponseStream::decode(body.into_data_stream(), encoding.clone(), None)
+            .with_deadline(deadline);
+
+        Ok(Response {
+            message: messages,
+            headers: parts.headers,
+            trailers: HeaderMap::new(),
+            extensions: Extensions::new(),
+        })
+    })
+    .await
+}
+
+fn http_request(
+    method: Method,
+    url: &str,
+    content_type: Option<HeaderValue>,
+    body: Body,
+    options: &CallOptions,
+) -> Result<http::Request<Body>, ConnectError> {
+    let mut request = http::Request::builder()
+        .method(method)
+        .uri(url)
+        .body(body)
+        .map_err(|e| ConnectError::internal(format!("Invalid request: {e}")))?;
+
+    let headers = request.headers_mut();
+
+    if let Some(content_type) = content_type {
+        headers.insert(CONTENT_TYPE, content_type);
+    }
+
+    headers.extend(options.request_headers());
+
+    Ok(request)
+}
+
+/// The error a failed (non-2xx) response stands for, from its Connect error JSON when there's
+/// one, and from its HTTP status otherwise. The response's headers become the error's metadata.
+pub fn error_from_response(status: StatusCode, headers: HeaderMap, body: &[u8]) -> ConnectError {
+    let fallback = code_from_status(status);
+
+    let mut error = match error_body(&headers, body) {
+        Some(body) => body.into_error(fallback),
+        None => ConnectError::new(
+            fallback,
+            status.canonical_reason().unwrap_or(status.as_str()),
+        ),
+    };
+
+    *error.metadata = headers;
+    error
+}
+
+/// The Connect error JSON of a response, if it has one
+fn error_body(headers: &HeaderMap, body: &[u8]) -> Option<ErrorBody> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+    if !mime.eq_ignore_ascii_case("application/json") {
+        return None;
+    }
+
+    let encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let body = match Compression::from_name(encoding).ok()? {
+        Some(compression) => compression.decompress(body).ok()?,
+        None => body.to_vec(),
+    };
+
+    serde_json::from_slice(&body).ok()
+}
+
+// https://connectrpc.com/docs/protocol/#http-to-error-code
+fn code_from_status(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST => Code::Internal,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::Unimplemented,
+        StatusCode::TOO_MANY_REQUESTS
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => Code::Unavailable,
+        _ => Code::Unknown,
+    }
+}
+
+/// The query of a `GET` request for `message`, already encoded in `encoding`
+///
+/// https://connectrpc.com/docs/protocol/#unary-get-request
+fn get_query(encoding: &Encoding, message: &[u8]) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .append_pair("connect", "v1")
+        .append_pair("encoding", encoding.name())
+        .append_pair("base64", "1")
+        .append_pair("message", &URL_SAFE_NO_PAD.encode(message))
+        .finish()
+}
+
+/// A unary response, its `trailer-`-prefixed headers split off as trailers
+fn unary_response<T>(
+    encoding: &Encoding,
+    headers: HeaderMap,
+    body: &[u8],
+) -> Result<Response<T>, ConnectError>
+where
+    T: ConnectMessageProto,
+{
+    let message = encoding.decode_response(body)?;
+
+    let mut response = Response::new(message);
+
+    for (name, value) in &headers {
+        match name.as_str().strip_prefix(UNARY_TRAILER_PREFIX) {
+            Some(trailer) => {
+                if let Ok(trailer) = HeaderName::try_from(trailer) {
+                    response.trailers.append(trailer, value.clone());
+                }
+            }
+            None => {
+                response.headers.append(name, value.clone());
+            }
+        }
+    }
+
+    Ok(response)
+}
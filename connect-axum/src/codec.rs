@@ -0,0 +1,398 @@
+//! Codecs: how messages are written on the wire, named after the suffix of the content type
+//! (`json` for `application/json`, `application/connect+json` and `application/grpc+json`).
+//! JSON and Protobuf are built in, and routers accept others registered with
+//! [`RouterOptions::codec`](crate::RouterOptions::codec).
+//!
+//! https://connectrpc.com/docs/protocol/#unary-request (Content-Type)
+use std::sync::Arc;
+
+use crate::message::SerdeImpls;
+use crate::{ConnectError, ConnectMessageProto};
+
+pub use erased_serde;
+
+/// Encodes messages to bytes and decodes them back, for content types ending in its name
+///
+/// ```ignore
+/// struct MsgpackCodec;
+///
+/// impl Codec for MsgpackCodec {
+///     fn name(&self) -> &str {
+///         "msgpack"
+///     }
+///
+///     fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, ConnectError> {
+///         rmp_serde::to_vec_named(message.as_serialize())
+///             .map_err(|e| ConnectError::internal(format!("MessagePack encode failed: {e}")))
+///     }
+///
+///     fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), ConnectError> {
+///         let mut deserializer = rmp_serde::Deserializer::new(bytes);
+///         message
+///             .deserialize(&mut <dyn erased_serde::Deserializer>::erase(&mut deserializer))
+///             .map_err(|e| ConnectError::invalid_argument(format!("Invalid MessagePack: {e}")))
+///     }
+/// }
+/// ```
+pub trait Codec: Send + Sync + 'static {
+    /// The name in content types, like `msgpack` for `application/msgpack`
+    fn name(&self) -> &str;
+
+    /// Whether the codec goes through the messages' serde impls, which some messages don't
+    /// have: calls with those fail with `internal` rather than reaching the codec. Codecs
+    /// working from Protobuf bytes (and descriptors) return `false`.
+    fn uses_serde(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, ConnectError>;
+
+    fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), ConnectError>;
+}
+
+/// A message for a [`Codec`] to encode, either as Protobuf or with serde (when it has serde
+/// impls, which codecs that use them can count on)
+pub trait EncodeMessage {
+    fn encode_proto(&self) -> Result<Vec<u8>, ConnectError>;
+
+    fn as_serialize(&self) -> &dyn erased_serde::Serialize;
+
+    /// The message's descriptor, embedded by the code generator
+    #[cfg(feature = "reflect")]
+    fn descriptor(&self) -> Option<&prost_reflect::MessageDescriptor>;
+}
+
+/// A message for a [`Codec`] to decode, either from Protobuf or with serde (when it has serde
+/// impls, which codecs that use them can count on)
+pub trait DecodeMessage {
+    fn decode_proto(&mut self, bytes: &[u8]) -> Result<(), ConnectError>;
+
+    fn deserialize(
+        &mut self,
+        deserializer: &mut dyn erased_serde::Deserializer<'_>,
+    ) -> Result<(), erased_serde::Error>;
+
+    /// The message's descriptor, embedded by the code generator
+    #[cfg(feature = "reflect")]
+    fn descriptor(&self) -> Option<&prost_reflect::MessageDescriptor>;
+}
+
+/// Protobuf's binary encoding, as `proto`
+pub struct ProtoCodec;
+
+impl Codec for ProtoCodec {
+    fn name(&self) -> &str {
+        "proto"
+    }
+
+    fn uses_serde(&self) -> bool {
+        false
+    }
+
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, ConnectError> {
+        message.encode_proto()
+    }
+
+    fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), ConnectError> {
+        message.decode_proto(bytes)
+    }
+}
+
+/// JSON through the messages' serde impls, as `json`
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, ConnectError> {
+        serde_json::to_vec(message.as_serialize())
+            .map_err(|e| ConnectError::internal(format!("JSON encode failed: {e}")))
+    }
+
+    fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), ConnectError> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            ConnectError::invalid_argument(format!("Invalid JSON: {e}"))
+        };
+
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        message
+            .deserialize(&mut <dyn erased_serde::Deserializer>::erase(
+                &mut deserializer,
+            ))
+            .map_err(|e| invalid(&e))?;
+
+        deserializer.end().map_err(|e| invalid(&e))
+    }
+}
+
+/// The codec of a call's messages, along with the serde impls of the method's messages when
+/// they have some, and the method's descriptor when it's known
+#[derive(Clone)]
+pub struct Encoding {
+    codec: Arc<dyn Codec>,
+    request_serde: Option<SerdeImpls>,
+    response_serde: Option<SerdeImpls>,
+    #[cfg(feature = "reflect")]
+    method: Option<prost_reflect::MethodDescriptor>,
+}
+
+/// Which of a method's messages is being encoded or decoded
+#[derive(Clone, Copy)]
+enum Side {
+    Request,
+    Response,
+}
+
+impl Encoding {
+    pub fn new(codec: Arc<dyn Codec>) -> Self {
+        Self {
+            codec,
+            request_serde: None,
+            response_serde: None,
+            #[cfg(feature = "reflect")]
+            method: None,
+        }
+    }
+
+    pub fn json() -> Self {
+        Self::new(Arc::new(JsonCodec))
+    }
+
+    pub fn proto() -> Self {
+        Self::new(Arc::new(ProtoCodec))
+    }
+
+    /// The codec's name, like `json`
+    pub fn name(&self) -> &str {
+        self.codec.name()
+    }
+
+    /// Use the serde impls of the method's request and response messages, found with
+    /// [`serde_impls!`](crate::serde_impls), for codecs that need them
+    pub fn with_serde(mut self, request: Option<SerdeImpls>, response: Option<SerdeImpls>) -> Self {
+        self.request_serde = request;
+        self.response_serde = response;
+        self
+    }
+
+    #[cfg(feature = "reflect")]
+    pub(crate) fn with_method(mut self, method: Option<prost_reflect::MethodDescriptor>) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Decode a request message
+    pub fn decode_request<T>(&self, bytes: &[u8]) -> Result<T, ConnectError>
+    where
+        T: ConnectMessageProto,
+    {
+        self.decode(bytes, Side::Request)
+    }
+
+    /// Encode a request message
+    pub fn encode_request<T>(&self, message: &T) -> Result<Vec<u8>, ConnectError>
+    where
+        T: ConnectMessageProto,
+    {
+        self.encode(message, Side::Request)
+    }
+
+    /// Decode a response message
+    pub fn decode_response<T>(&self, bytes: &[u8]) -> Result<T, ConnectError>
+    where
+        T: ConnectMessageProto,
+    {
+        self.decode(bytes, Side::Response)
+    }
+
+    /// Encode a response message
+    pub fn encode_response<T>(&self, message: &T) -> Result<Vec<u8>, ConnectError>
+    where
+        T: ConnectMessageProto,
+    {
+        self.encode(message, Side::Response)
+    }
+
+    #[cfg_attr(not(feature = "reflect"), allow(unused_variables))]
+    fn decode<T>(&self, bytes: &[u8], side: Side) -> Result<T, ConnectError>
+    where
+        T: ConnectMessageProto,
+    {
+        let serde = self.serde::<T>(side)?;
+
+        let mut decoded = Decoded {
+            message: None,
+            serde,
+            #[cfg(feature = "reflect")]
+            descriptor: self.descriptor(side),
+        };
+
+        self.codec.decode(bytes, &mut decoded)?;
+
+        decoded.message.ok_or_else(|| {
+            ConnectError::internal(format!("The {} codec decoded no message", self.name()))
+        })
+    }
+
+    #[cfg_attr(not(feature = "reflect"), allow(unused_variables))]
+    fn encode<T>(&self, message: &T, side: Side) -> Result<Vec<u8>, ConnectError>
+    where
+        T: ConnectMessageProto,
+    {
+        let serde = self.serde::<T>(side)?;
+
+        self.codec.encode(&Encoded {
+            message,
+            serde,
+            #[cfg(feature = "reflect")]
+            descriptor: self.descriptor(side),
+        })
+    }
+
+    /// The serde impls of the message on `side`, failing if the codec needs them and it has none
+    fn serde<T>(&self, side: Side) -> Result<Option<SerdeImpls>, ConnectError> {
+        let serde = match side {
+            Side::Request => self.request_serde,
+            Side::Response => self.response_serde,
+        };
+
+        if serde.is_none() && self.codec.uses_serde() {
+            return Err(ConnectError::internal(format!(
+                "{} has no serde impls, which the {} codec needs",
+                std::any::type_name::<T>(),
+                self.name()
+            )));
+        }
+
+        Ok(serde)
+    }
+
+    /// Only codecs working through reflection care which side the message is on
+    #[cfg(feature = "reflect")]
+    fn descriptor(&self, side: Side) -> Option<prost_reflect::MessageDescriptor> {
+        self.method.as_ref().map(|method| match side {
+            Side::Request => method.input(),
+            Side::Response => method.output(),
+        })
+    }
+}
+
+struct Encoded<'a, T> {
+    message: &'a T,
+    serde: Option<SerdeImpls>,
+    #[cfg(feature = "reflect")]
+    descriptor: Option<prost_reflect::MessageDescriptor>,
+}
+
+impl<T> EncodeMessage for Encoded<'_, T>
+where
+    T: ConnectMessageProto,
+{
+    fn encode_proto(&self) -> Result<Vec<u8>, ConnectError> {
+        self.message.encode_proto()
+    }
+
+    fn as_serialize(&self) -> &dyn erased_serde::Serialize {
+        self.serde
+            .and_then(|serde| serde.serialize(self.message))
+            .unwrap_or(&NoSerialize)
+    }
+
+    #[cfg(feature = "reflect")]
+    fn descriptor(&self) -> Option<&prost_reflect::MessageDescriptor> {
+        self.descriptor.as_ref()
+    }
+}
+
+struct Decoded<T> {
+    message: Option<T>,
+    serde: Option<SerdeImpls>,
+    #[cfg(feature = "reflect")]
+    descriptor: Option<prost_reflect::MessageDescriptor>,
+}
+
+impl<T> DecodeMessage for Decoded<T>
+where
+    T: ConnectMessageProto,
+{
+    fn decode_proto(&mut self, bytes: &[u8]) -> Result<(), ConnectError> {
+        self.message = Some(T::decode_proto(bytes)?);
+        Ok(())
+    }
+
+    fn deserialize(
+        &mut self,
+        deserializer: &mut dyn erased_serde::Deserializer<'_>,
+    ) -> Result<(), erased_serde::Error> {
+        let serde = self
+            .serde
+            .ok_or_else(|| serde::de::Error::custom(NO_SERDE_IMPLS))?;
+
+        self.message = Some(serde.deserialize(deserializer)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "reflect")]
+    fn descriptor(&self) -> Option<&prost_reflect::MessageDescriptor> {
+        self.descriptor.as_ref()
+    }
+}
+
+const NO_SERDE_IMPLS: &str = "The message has no serde impls";
+
+/// Stands in for messages without serde impls, which codecs declaring they use serde never see
+struct NoSerialize;
+
+impl serde::Serialize for NoSerialize {
+    fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom(NO_SERDE_IMPLS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+
+    #[test]
+    fn proto_needs_no_serde_impls() {
+        let timestamp = prost_types::Timestamp {
+            seconds: 1,
+            nanos: 0,
+        };
+
+        let encoding = Encoding::proto();
+        let bytes = encoding.encode_response(&timestamp).unwrap();
+
+        assert_eq!(
+            encoding
+                .decode_response::<prost_types::Timestamp>(&bytes)
+                .unwrap(),
+            timestamp
+        );
+    }
+
+    #[test]
+    fn json_needs_serde_impls() {
+        let encoding = Encoding::json();
+
+        let error = encoding
+            .encode_response(&prost_types::Timestamp::default())
+            .unwrap_err();
+        assert_eq!(error.code(), Code::Internal);
+
+        let error = encoding
+            .decode_request::<prost_types::Timestamp>(b"{}")
+            .unwrap_err();
+        assert_eq!(error.code(), Code::Internal);
+    }
+
+    #[test]
+    fn json_uses_the_serde_impls() {
+        let encoding = Encoding::json().with_serde(None, crate::serde_impls!(crate::Empty));
+
+        assert_eq!(encoding.encode_response(&crate::Empty {}).unwrap(), b"{}");
+    }
+}
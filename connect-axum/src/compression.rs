@@ -0,0 +1,208 @@
+// https://connectrpc.com/docs/protocol/#unary-request (Content-Encoding, Accept-Encoding)
+// https://connectrpc.com/docs/protocol/#streaming-request (Connect-Content-Encoding, Connect-Accept-Encoding)
+// With every algorithm disabled, `Compression` has no variants and most of this is dead code
+#![cfg_attr(
+    not(any(feature = "gzip", feature = "br", feature = "zstd")),
+    allow(unused, unreachable_code)
+)]
+
+use crate::{Code, ConnectError};
+
+/// Messages smaller than this aren't worth compressing
+pub const MIN_COMPRESS_BYTES: usize = 1024;
+
+const IDENTITY: &str = "identity";
+
+/// A compression algorithm, each enabled by the cargo feature of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "br")]
+    Brotli,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Every enabled algorithm, in order of preference
+    pub const ALL: &'static [Compression] = &[
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+        #[cfg(feature = "br")]
+        Compression::Brotli,
+        #[cfg(feature = "gzip")]
+        Compression::Gzip,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => "gzip",
+            #[cfg(feature = "br")]
+            Compression::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Look up the algorithm named by a `Content-Encoding` (or similar) value, where `identity`
+    /// means no compression. Unsupported algorithms are an `unimplemented` error, as the
+    /// protocol requires.
+    pub fn from_name(name: &str) -> Result<Option<Self>, ConnectError> {
+        let name = name.trim();
+
+        if name.is_empty() || name.eq_ignore_ascii_case(IDENTITY) {
+            return Ok(None);
+        }
+
+        Self::ALL
+            .iter()
+            .find(|compression| name.eq_ignore_ascii_case(compression.name()))
+            .copied()
+            .map(Some)
+            .ok_or_else(|| {
+                ConnectError::new(
+                    Code::Unimplemented,
+                    format!(
+                        "Unsupported compression: {name} (supported: {})",
+                        accept_encoding()
+                    ),
+                )
+            })
+    }
+
+    /// Pick the first supported algorithm from an `Accept-Encoding` (or similar) value
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        accept_encoding
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let name = parts.next()?.trim();
+
+                // Skip anything explicitly refused with q=0
+                let refused = parts.any(|param| {
+                    param
+                        .trim()
+                        .strip_prefix("q=")
+                        .and_then(|q| q.trim().parse::<f32>().ok())
+                        .is_some_and(|q| q == 0.0)
+                });
+
+                if refused { None } else { Some(name) }
+            })
+            .find_map(|name| Self::from_name(name).ok().flatten())
+    }
+
+    pub fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, ConnectError> {
+        use std::io::Write;
+
+        let map_err = |e: std::io::Error| {
+            ConnectError::internal(format!("{} compression failed: {e}", self.name()))
+        };
+
+        match *self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes).map_err(map_err)?;
+                encoder.finish().map_err(map_err)
+            }
+            #[cfg(feature = "br")]
+            Compression::Brotli => {
+                let mut compressed = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+                    encoder.write_all(bytes).map_err(map_err)?;
+                }
+                Ok(compressed)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::encode_all(bytes, 0).map_err(map_err),
+        }
+    }
+
+    pub fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, ConnectError> {
+        self.decompress_limited(bytes, usize::MAX)
+    }
+
+    /// Decompress at most `limit` bytes, failing with `resource_exhausted` as soon as the output
+    /// grows past it rather than after inflating all of it (like a compression bomb would)
+    pub fn decompress_limited(&self, bytes: &[u8], limit: usize) -> Result<Vec<u8>, ConnectError> {
+        use std::io::Read;
+
+        // Read one byte past the limit, to tell a message of exactly `limit` bytes from a bigger one
+        let take = u64::try_from(limit).unwrap_or(u64::MAX).saturating_add(1);
+        let mut decompressed = Vec::new();
+
+        let result: std::io::Result<usize> = match *self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => flate2::read::GzDecoder::new(bytes)
+                .take(take)
+                .read_to_end(&mut decompressed),
+            #[cfg(feature = "br")]
+            Compression::Brotli => brotli::Decompressor::new(bytes, 4096)
+                .take(take)
+                .read_to_end(&mut decompressed),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::Decoder::new(bytes)
+                .and_then(|decoder| decoder.take(take).read_to_end(&mut decompressed)),
+        };
+
+        result.map_err(|e| {
+            ConnectError::invalid_argument(format!("{} decompression failed: {e}", self.name()))
+        })?;
+
+        if decompressed.len() > limit {
+            return Err(ConnectError::new(
+                Code::ResourceExhausted,
+                format!("Decompressed message exceeds the limit of {limit} bytes"),
+            ));
+        }
+
+        Ok(decompressed)
+    }
+}
+
+/// The enabled algorithms as an `Accept-Encoding` value
+pub fn accept_encoding() -> String {
+    Compression::ALL
+        .iter()
+        .map(Compression::name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// gzip is the only algorithm enabled by default
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_the_first_supported_algorithm() {
+        assert_eq!(Compression::negotiate("gzip"), Some(Compression::Gzip));
+        assert_eq!(
+            Compression::negotiate("snappy, gzip;q=0.5"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::negotiate(" GZIP "), Some(Compression::Gzip));
+    }
+
+    #[test]
+    fn negotiates_nothing_refused_or_unsupported() {
+        assert_eq!(Compression::negotiate(""), None);
+        assert_eq!(Compression::negotiate("identity"), None);
+        assert_eq!(Compression::negotiate("snappy, lz4"), None);
+        assert_eq!(Compression::negotiate("gzip;q=0"), None);
+        assert_eq!(Compression::negotiate("gzip; q=0.0, identity"), None);
+    }
+
+    #[test]
+    fn unsupported_algorithms_are_unimplemented() {
+        assert_eq!(Compression::from_name("identity").unwrap(), None);
+
+        let error = Compression::from_name("snappy").unwrap_err();
+        assert_eq!(error.code(), Code::Unimplemented);
+    }
+}
@@ -0,0 +1,95 @@
+// https://connectrpc.com/docs/protocol/#unary-request (Timeout)
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::{Code, ConnectError, ResponseStream};
+use futures_util::Stream;
+use tokio::time::Sleep;
+
+/// The protocol allows at most 10 digits of milliseconds in `connect-timeout-ms`
+pub const MAX_TIMEOUT_DIGITS: usize = 10;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// The instant by which the current RPC must finish, if the client set a timeout.
+/// Only available from within a handler (including while its response stream is polled).
+pub fn deadline() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// The time left before the current RPC's deadline, for propagating it to downstream calls
+pub fn remaining() -> Option<Duration> {
+    deadline().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// The deadline for a request received just now with the given `connect-timeout-ms`
+pub fn from_timeout_ms(timeout_ms: Option<u64>) -> Option<Instant> {
+    timeout_ms.map(|timeout_ms| Instant::now() + Duration::from_millis(timeout_ms))
+}
+
+/// Run a handler, cancelling it with `deadline_exceeded` if it doesn't finish by the deadline
+pub async fn run<T>(
+    deadline: Option<Instant>,
+    handler: impl Future<Output = Result<T, ConnectError>>,
+) -> Result<T, ConnectError> {
+    let Some(deadline) = deadline else {
+        return handler.await;
+    };
+
+    DEADLINE
+        .scope(deadline, tokio::time::timeout_at(deadline.into(), handler))
+        .await
+        .unwrap_or_else(|_| Err(deadline_exceeded()))
+}
+
+impl<T> ResponseStream<T>
+where
+    T: Send + 'static,
+{
+    /// End the stream with `deadline_exceeded` if it's still going at the deadline
+    pub fn with_deadline(self, deadline: Option<Instant>) -> Self {
+        match deadline {
+            Some(deadline) => self.wrap(|inner| DeadlineStream {
+                inner,
+                deadline,
+                sleep: Box::pin(tokio::time::sleep_until(deadline.into())),
+                done: false,
+            }),
+            None => self,
+        }
+    }
+}
+
+struct DeadlineStream<T> {
+    inner: ResponseStream<T>,
+    deadline: Instant,
+    sleep: Pin<Box<Sleep>>,
+    done: bool,
+}
+
+impl<T> Stream for DeadlineStream<T> {
+    type Item = Result<T, ConnectError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        if self.sleep.as_mut().poll(cx).is_ready() {
+            self.done = true;
+            return Poll::Ready(Some(Err(deadline_exceeded())));
+        }
+
+        // Keep the deadline visible to the handler's code while its stream is polled
+        let this = &mut *self;
+        DEADLINE.sync_scope(this.deadline, || Pin::new(&mut this.inner).poll_next(cx))
+    }
+}
+
+fn deadline_exceeded() -> ConnectError {
+    ConnectError::new(Code::DeadlineExceeded, "Deadline exceeded")
+}
@@ -1,23 +1,173 @@
-use crate::{APPLICATION_CONNECT_JSON, APPLICATION_CONNECT_PROTO, Code, ConnectError, Encoding};
+use crate::compression::{Compression, MIN_COMPRESS_BYTES};
+use crate::envelope::{self, EndStreamMessage, Envelope, FLAG_COMPRESSED};
+use crate::{
+    CONNECT_CONTENT_ENCODING, Code, ConnectError, ConnectMessageProto, Encoding, ErrorDetail,
+    MessageLimits, ResponseStream,
+};
 use axum::{
     Json,
     response::{IntoResponse, Response},
 };
-use http::{HeaderMap, HeaderValue, StatusCode, header::CONTENT_TYPE};
-use serde::Serialize;
+use futures_util::{StreamExt, stream};
+use http::{
+    HeaderMap, HeaderName, HeaderValue, StatusCode,
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+};
+use serde::{Deserialize, Serialize};
+
+// https://connectrpc.com/docs/protocol/#unary-response (Trailers)
+pub(crate) const UNARY_TRAILER_PREFIX: &str = "trailer-";
+
+/// `application/{prefix}{codec}`, like `application/connect+json`
+pub(crate) fn content_type(prefix: &str, encoding: &Encoding) -> HeaderValue {
+    HeaderValue::try_from(format!("application/{prefix}{}", encoding.name()))
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"))
+}
 
+/// Encode a unary response, sending its trailers as `trailer-`-prefixed headers
 pub fn encode_http_response(
-    message: Vec<u8>,
+    response: crate::Response<Vec<u8>>,
     encoding: Encoding,
+    compression: Option<Compression>,
+    limits: MessageLimits,
 ) -> Result<Response, ConnectError> {
-    let content_type = match encoding {
-        Encoding::Json => APPLICATION_CONNECT_JSON,
-        Encoding::Proto => APPLICATION_CONNECT_PROTO,
+    limits.check_send(response.message.len())?;
+
+    let content_type = content_type("", &encoding);
+
+    let crate::Response {
+        message,
+        mut headers,
+        trailers,
+        extensions,
+    } = response;
+
+    headers.insert(CONTENT_TYPE, content_type);
+
+    for (name, value) in &trailers {
+        let name = HeaderName::try_from(format!("{UNARY_TRAILER_PREFIX}{name}"))
+            .map_err(|e| ConnectError::internal(format!("Invalid trailer name: {e}")))?;
+        headers.append(name, value.clone());
+    }
+
+    let message = match compression {
+        Some(compression) if message.len() >= MIN_COMPRESS_BYTES => {
+            headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(compression.name()),
+            );
+            compression.compress(&message)?
+        }
+        _ => message,
+    };
+
+    Ok((StatusCode::OK, headers, extensions, message).into_response())
+}
+
+/// Encode a stream of response messages as enveloped frames, finishing with the end-stream message
+/// (which carries the response's trailers). Errors (from the stream or from encoding) end the
+/// stream and are reported in the end-stream message.
+pub fn encode_stream_response<T>(
+    response: crate::Response<ResponseStream<T>>,
+    encoding: Encoding,
+    compression: Option<Compression>,
+    limits: MessageLimits,
+) -> Result<Response, ConnectError>
+where
+    T: ConnectMessageProto,
+{
+    let content_type = content_type("connect+", &encoding);
+
+    let crate::Response {
+        message: messages,
+        mut headers,
+        trailers,
+        extensions,
+    } = response;
+
+    headers.insert(CONTENT_TYPE, content_type);
+
+    if let Some(compression) = compression {
+        headers.insert(
+            HeaderName::from_static(CONNECT_CONTENT_ENCODING),
+            HeaderValue::from_static(compression.name()),
+        );
+    }
+
+    let envelopes = stream::unfold(Some((messages, trailers)), move |state| {
+        let encoding = encoding.clone();
+
+        async move {
+            let (mut messages, trailers) = state?;
+
+            let encoded = match messages.next().await {
+                Some(Ok(message)) => encoding.encode_response(&message),
+                Some(Err(e)) => Err(e),
+                None => return Some((end_stream_envelope(None, trailers), None)),
+            };
+
+            match encoded.and_then(|bytes| message_envelope(bytes, compression, limits)) {
+                Ok(envelope) => Some((envelope, Some((messages, trailers)))),
+                Err(e) => Some((end_stream_envelope(Some(e), trailers), None)),
+            }
+        }
+    });
+
+    Ok((
+        StatusCode::OK,
+        headers,
+        extensions,
+        envelope::into_body(envelopes),
+    )
+        .into_response())
+}
+
+/// An envelope for a response message, if it's within the send limit and fits in an envelope
+pub(crate) fn message_envelope(
+    message: Vec<u8>,
+    compression: Option<Compression>,
+    limits: MessageLimits,
+) -> Result<Envelope, ConnectError> {
+    limits.check_send(message.len())?;
+
+    let envelope = match compression {
+        Some(compression) if message.len() >= MIN_COMPRESS_BYTES => {
+            Envelope::new(FLAG_COMPRESSED, compression.compress(&message)?)
+        }
+        _ => Envelope::message(message),
     };
 
-    let headers = HeaderMap::from_iter([(CONTENT_TYPE, HeaderValue::from_static(content_type))]);
+    // Caught here so the error ends the stream, instead of the body failing while it's written
+    envelope.payload_len()?;
+
+    Ok(envelope)
+}
+
+/// A streaming response without any messages, only the end-stream message reporting `error`,
+/// for streaming requests that fail before their stream starts
+pub(crate) fn end_stream_error_response(
+    error: ConnectError,
+    content_type: HeaderValue,
+) -> Response {
+    let envelope = end_stream_envelope(Some(error), HeaderMap::new());
+
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, content_type)],
+        envelope::into_body(stream::iter([envelope])),
+    )
+        .into_response()
+}
+
+fn end_stream_envelope(mut error: Option<ConnectError>, mut metadata: HeaderMap) -> Envelope {
+    // The error's metadata is sent alongside it, as the trailers of the stream
+    if let Some(e) = error.as_mut() {
+        metadata.extend(std::mem::take(&mut *e.metadata));
+    }
+
+    let end_stream = EndStreamMessage { error, metadata };
 
-    Ok((StatusCode::OK, headers, message).into_response())
+    Envelope::end_stream(&end_stream)
 }
 
 // https://connectrpc.com/docs/protocol/#error-codes
@@ -68,21 +218,81 @@ impl From<Code> for &'static str {
     }
 }
 
-impl IntoResponse for ConnectError {
-    fn into_response(self) -> Response {
-        #[derive(Serialize)]
-        struct ErrorBody {
-            code: &'static str,
-            message: String,
+// https://connectrpc.com/docs/protocol/#error-codes
+impl TryFrom<&str> for Code {
+    type Error = ConnectError;
+
+    fn try_from(code: &str) -> Result<Self, Self::Error> {
+        match code {
+            "canceled" => Ok(Code::Canceled),
+            "unknown" => Ok(Code::Unknown),
+            "invalid_argument" => Ok(Code::InvalidArgument),
+            "deadline_exceeded" => Ok(Code::DeadlineExceeded),
+            "not_found" => Ok(Code::NotFound),
+            "already_exists" => Ok(Code::AlreadyExists),
+            "permission_denied" => Ok(Code::PermissionDenied),
+            "resource_exhausted" => Ok(Code::ResourceExhausted),
+            "failed_precondition" => Ok(Code::FailedPrecondition),
+            "aborted" => Ok(Code::Aborted),
+            "out_of_range" => Ok(Code::OutOfRange),
+            "unimplemented" => Ok(Code::Unimplemented),
+            "internal" => Ok(Code::Internal),
+            "unavailable" => Ok(Code::Unavailable),
+            "data_loss" => Ok(Code::DataLoss),
+            "unauthenticated" => Ok(Code::Unauthenticated),
+            other => Err(ConnectError::internal(format!(
+                "Unknown error code: {other}"
+            ))),
         }
+    }
+}
 
-        let status_code: StatusCode = self.code.into();
+/// The JSON representation of a [`ConnectError`]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ErrorBody {
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    details: Vec<ErrorDetail>,
+}
+
+impl From<&ConnectError> for ErrorBody {
+    fn from(error: &ConnectError) -> Self {
+        let code: &'static str = error.code.into();
+
+        Self {
+            code: code.to_string(),
+            message: error.message.clone(),
+            details: error.details.clone(),
+        }
+    }
+}
+
+impl ErrorBody {
+    /// The error, with the `fallback` code if the body's is missing or unknown
+    pub(crate) fn into_error(self, fallback: Code) -> ConnectError {
+        let code = Code::try_from(self.code.as_str()).unwrap_or(fallback);
+
+        ConnectError {
+            details: self.details,
+            ..ConnectError::new(code, self.message)
+        }
+    }
+}
+
+impl From<ErrorBody> for ConnectError {
+    fn from(body: ErrorBody) -> Self {
+        body.into_error(Code::Unknown)
+    }
+}
 
-        let error_body = ErrorBody {
-            code: self.code.into(),
-            message: self.message,
-        };
+impl IntoResponse for ConnectError {
+    fn into_response(mut self) -> Response {
+        let status_code: StatusCode = self.code.into();
+        let metadata = std::mem::take(&mut self.metadata);
 
-        (status_code, Json(error_body)).into_response()
+        (status_code, *metadata, Json(ErrorBody::from(&self))).into_response()
     }
 }
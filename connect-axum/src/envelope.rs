@@ -0,0 +1,367 @@
+//! The Connect envelope used to frame each message of a streaming request or response:
+//! one byte of flags, four bytes of big-endian payload length, then the payload itself.
+//!
+//! https://connectrpc.com/docs/protocol/#streaming-request
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use crate::encode::ErrorBody;
+use crate::{Code, ConnectError};
+use axum::body::{Body, BodyDataStream};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+/// The payload is compressed with the stream's negotiated compression
+pub const FLAG_COMPRESSED: u8 = 0b0000_0001;
+
+/// The payload is the JSON end-stream message, always the last envelope of a response stream
+pub const FLAG_END_STREAM: u8 = 0b0000_0010;
+
+/// The length of the flags byte plus the big-endian payload length
+pub const PREFIX_LEN: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub flags: u8,
+    pub payload: Bytes,
+}
+
+impl Envelope {
+    pub fn new(flags: u8, payload: impl Into<Bytes>) -> Self {
+        Self {
+            flags,
+            payload: payload.into(),
+        }
+    }
+
+    /// An envelope holding an (uncompressed) encoded message
+    pub fn message(payload: impl Into<Bytes>) -> Self {
+        Self::new(0, payload)
+    }
+
+    /// An envelope holding the end-stream message
+    pub fn end_stream(end_stream: &EndStreamMessage) -> Self {
+        Self::new(FLAG_END_STREAM, end_stream.to_json())
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & FLAG_COMPRESSED != 0
+    }
+
+    pub fn is_end_stream(&self) -> bool {
+        self.flags & FLAG_END_STREAM != 0
+    }
+
+    /// Parse the payload of an end-stream envelope
+    pub fn into_end_stream(self) -> Result<EndStreamMessage, ConnectError> {
+        if !self.is_end_stream() {
+            return Err(ConnectError::internal("Not an end-stream message"));
+        }
+
+        EndStreamMessage::from_json(&self.payload)
+    }
+
+    /// The length of the payload as written in the prefix, which only has four bytes for it
+    pub fn payload_len(&self) -> Result<u32, ConnectError> {
+        u32::try_from(self.payload.len()).map_err(|_| {
+            ConnectError::new(
+                Code::ResourceExhausted,
+                format!(
+                    "Message of {} bytes exceeds the limit of {} bytes of an envelope",
+                    self.payload.len(),
+                    u32::MAX
+                ),
+            )
+        })
+    }
+
+    /// The envelope as bytes on the wire
+    pub fn encode(&self) -> Result<Bytes, ConnectError> {
+        let len = self.payload_len()?;
+
+        let mut buf = BytesMut::with_capacity(PREFIX_LEN + self.payload.len());
+        buf.put_u8(self.flags);
+        buf.put_u32(len);
+        buf.put_slice(&self.payload);
+        Ok(buf.freeze())
+    }
+}
+
+/// The final message of a response stream, reporting how the stream ended
+#[derive(Debug, Default)]
+pub struct EndStreamMessage {
+    pub error: Option<ConnectError>,
+    pub metadata: HeaderMap,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct EndStreamJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorBody>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, Vec<String>>,
+}
+
+impl EndStreamMessage {
+    pub fn to_json(&self) -> Vec<u8> {
+        let mut metadata = BTreeMap::<String, Vec<String>>::new();
+
+        for (name, value) in &self.metadata {
+            if let Ok(value) = value.to_str() {
+                metadata
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(value.to_string());
+            }
+        }
+
+        let end_stream = EndStreamJson {
+            error: self.error.as_ref().map(ErrorBody::from),
+            metadata,
+        };
+
+        // Serializing maps and structs of strings can't fail
+        serde_json::to_vec(&end_stream).unwrap_or_default()
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, ConnectError> {
+        let end_stream: EndStreamJson = serde_json::from_slice(bytes)
+            .map_err(|e| ConnectError::internal(format!("Invalid end-stream message: {e}")))?;
+
+        let mut metadata = HeaderMap::new();
+
+        for (name, values) in end_stream.metadata {
+            let Ok(name) = HeaderName::try_from(name) else {
+                continue;
+            };
+
+            for value in values {
+                if let Ok(value) = HeaderValue::try_from(value) {
+                    metadata.append(name.clone(), value);
+                }
+            }
+        }
+
+        Ok(Self {
+            error: end_stream.error.map(ConnectError::from),
+            metadata,
+        })
+    }
+}
+
+/// A body that writes each envelope as soon as the stream yields it, failing on one too big to
+/// be framed
+pub fn into_body(envelopes: impl Stream<Item = Envelope> + Send + 'static) -> Body {
+    Body::from_stream(envelopes.map(|envelope| envelope.encode()))
+}
+
+/// Incrementally splits a body into envelopes as its bytes arrive
+pub struct EnvelopeStream {
+    body: BodyDataStream,
+    buffer: BytesMut,
+    body_done: bool,
+    max_payload_len: usize,
+}
+
+impl EnvelopeStream {
+    pub fn new(body: Body) -> Self {
+        Self {
+            body: body.into_data_stream(),
+            buffer: BytesMut::new(),
+            body_done: false,
+            max_payload_len: usize::MAX,
+        }
+    }
+
+    /// Fail with `resource_exhausted` on any envelope with a bigger payload, as soon as its
+    /// prefix arrives (so the payload is never buffered)
+    pub fn with_max_payload_len(mut self, max_payload_len: usize) -> Self {
+        self.max_payload_len = max_payload_len;
+        self
+    }
+
+    /// The payload length announced by the prefix of the next envelope, once it's arrived
+    fn next_payload_len(&self) -> Option<usize> {
+        let prefix = self.buffer.get(..PREFIX_LEN)?;
+
+        Some(u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize)
+    }
+
+    fn next_envelope(&mut self) -> Option<Envelope> {
+        let len = self.next_payload_len()?;
+
+        if self.buffer.len() < PREFIX_LEN + len {
+            return None;
+        }
+
+        let flags = self.buffer.get_u8();
+        self.buffer.advance(PREFIX_LEN - 1);
+        let payload = self.buffer.split_to(len).freeze();
+
+        Some(Envelope { flags, payload })
+    }
+}
+
+impl Stream for EnvelopeStream {
+    type Item = Result<Envelope, ConnectError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(len) = self.next_payload_len()
+                && len > self.max_payload_len
+            {
+                // Stop reading the body, which the rest of the stream can't be made sense of
+                self.body_done = true;
+                self.buffer.clear();
+                return Poll::Ready(Some(Err(ConnectError::new(
+                    Code::ResourceExhausted,
+                    format!(
+                        "Received message of {len} bytes exceeds the limit of {} bytes",
+                        self.max_payload_len
+                    ),
+                ))));
+            }
+
+            if let Some(envelope) = self.next_envelope() {
+                return Poll::Ready(Some(Ok(envelope)));
+            }
+
+            if self.body_done {
+                if self.buffer.is_empty() {
+                    return Poll::Ready(None);
+                }
+
+                let remaining = self.buffer.len();
+                self.buffer.clear();
+                return Poll::Ready(Some(Err(ConnectError::invalid_argument(format!(
+                    "Incomplete envelope: {remaining} trailing bytes"
+                )))));
+            }
+
+            match ready!(Pin::new(&mut self.body).poll_next(cx)) {
+                Some(Ok(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Some(Err(e)) => {
+                    self.body_done = true;
+                    self.buffer.clear();
+                    return Poll::Ready(Some(Err(ConnectError::internal(format!(
+                        "Failed to read body: {e}"
+                    )))));
+                }
+                None => self.body_done = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use std::convert::Infallible;
+
+    /// A body arriving in `chunks`
+    fn body(chunks: Vec<Vec<u8>>) -> Body {
+        Body::from_stream(stream::iter(chunks.into_iter().map(Ok::<_, Infallible>)))
+    }
+
+    fn encoded(flags: u8, payload: &[u8]) -> Vec<u8> {
+        Envelope::new(flags, payload.to_vec())
+            .encode()
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn encodes_the_prefix() {
+        assert_eq!(encoded(FLAG_COMPRESSED, b"hi"), [1, 0, 0, 0, 2, b'h', b'i']);
+    }
+
+    #[tokio::test]
+    async fn reads_envelopes_split_across_chunks() {
+        let bytes = [encoded(0, b"first"), encoded(0, b"second")].concat();
+
+        // One byte at a time, splitting the prefixes too
+        let chunks = bytes.iter().map(|byte| vec![*byte]).collect();
+        let envelopes: Vec<_> = EnvelopeStream::new(body(chunks)).collect().await;
+
+        let payloads: Vec<_> = envelopes
+            .into_iter()
+            .map(|envelope| envelope.unwrap().payload)
+            .collect();
+        assert_eq!(payloads, [&b"first"[..], &b"second"[..]]);
+    }
+
+    #[tokio::test]
+    async fn reads_several_envelopes_from_one_chunk() {
+        let bytes = [encoded(0, b""), encoded(FLAG_COMPRESSED, b"x")].concat();
+        let mut envelopes = EnvelopeStream::new(body(vec![bytes]));
+
+        let first = envelopes.next().await.unwrap().unwrap();
+        assert!(first.payload.is_empty());
+        assert!(!first.is_compressed());
+
+        let second = envelopes.next().await.unwrap().unwrap();
+        assert_eq!(second.payload, &b"x"[..]);
+        assert!(second.is_compressed());
+
+        assert!(envelopes.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_on_an_oversized_length_before_its_payload_arrives() {
+        // Only the prefix, announcing 4 GiB
+        let prefix = vec![0, 0xff, 0xff, 0xff, 0xff];
+        let mut envelopes = EnvelopeStream::new(body(vec![prefix])).with_max_payload_len(1024);
+
+        let error = envelopes.next().await.unwrap().unwrap_err();
+        assert_eq!(error.code(), Code::ResourceExhausted);
+        assert!(envelopes.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_on_a_truncated_envelope() {
+        let mut bytes = encoded(0, b"message");
+        bytes.truncate(bytes.len() - 1);
+        let mut envelopes = EnvelopeStream::new(body(vec![bytes]));
+
+        let error = envelopes.next().await.unwrap().unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn reads_the_end_stream_message() {
+        let mut metadata = HeaderMap::new();
+        metadata.insert("x-total", HeaderValue::from_static("2"));
+
+        let end_stream = EndStreamMessage {
+            error: Some(ConnectError::new(Code::NotFound, "gone")),
+            metadata,
+        };
+
+        let bytes = [
+            encoded(0, b"message"),
+            Envelope::end_stream(&end_stream).encode().unwrap().to_vec(),
+        ]
+        .concat();
+        let mut envelopes = EnvelopeStream::new(body(vec![bytes]));
+
+        assert!(!envelopes.next().await.unwrap().unwrap().is_end_stream());
+
+        let last = envelopes.next().await.unwrap().unwrap();
+        assert!(last.is_end_stream());
+
+        let end_stream = last.into_end_stream().unwrap();
+        let error = end_stream.error.unwrap();
+        assert_eq!(error.code(), Code::NotFound);
+        assert_eq!(error.message(), "gone");
+        assert_eq!(end_stream.metadata["x-total"], "2");
+    }
+
+    #[test]
+    fn only_end_stream_envelopes_are_end_stream_messages() {
+        assert!(Envelope::message(b"{}".to_vec()).into_end_stream().is_err());
+    }
+}
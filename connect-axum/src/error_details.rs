@@ -0,0 +1,280 @@
+//! Error details: arbitrary Protobuf messages attached to a [`ConnectError`] to describe it in
+//! more detail than a code and message can, like the standard `google.rpc` types below.
+//!
+//! https://connectrpc.com/docs/protocol/#error-end-stream
+use std::collections::HashMap;
+
+use crate::ConnectError;
+use base64::Engine;
+use base64::alphabet::STANDARD;
+use base64::engine::DecodePaddingMode;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD_NO_PAD};
+use prost::{Message, Name};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Detail values are sent unpadded, but padded values must be accepted too
+const STANDARD_INDIFFERENT_PAD: GeneralPurpose = GeneralPurpose::new(
+    &STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// A `google.protobuf.Any` attached to an error, sent as `{"type", "value", "debug"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    #[serde(rename = "type")]
+    type_name: String,
+    #[serde(
+        serialize_with = "serialize_base64",
+        deserialize_with = "deserialize_base64"
+    )]
+    value: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    debug: Option<serde_json::Value>,
+}
+
+impl ErrorDetail {
+    /// A detail from the fully-qualified name of a message type (without any
+    /// `type.googleapis.com/` prefix) and the message encoded as Protobuf
+    pub fn new(type_name: impl Into<String>, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            type_name: type_name.into(),
+            value: value.into(),
+            debug: None,
+        }
+    }
+
+    pub fn from_message<M: Name>(message: &M) -> Self {
+        Self::new(M::full_name(), message.encode_to_vec())
+    }
+
+    /// Attach a JSON representation of the detail, purely to help humans debugging the error
+    pub fn with_debug(mut self, debug: serde_json::Value) -> Self {
+        self.debug = Some(debug);
+        self
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn debug(&self) -> Option<&serde_json::Value> {
+        self.debug.as_ref()
+    }
+
+    pub fn is<M: Name>(&self) -> bool {
+        self.type_name == M::full_name()
+    }
+
+    /// Decode the detail as an `M`, or `None` if it's some other type of message
+    pub fn to_message<M: Name + Default>(&self) -> Option<Result<M, ConnectError>> {
+        if !self.is::<M>() {
+            return None;
+        }
+
+        Some(M::decode(self.value.as_slice()).map_err(|e| {
+            ConnectError::internal(format!("Invalid {} error detail: {e}", self.type_name))
+        }))
+    }
+}
+
+impl<M: Name> From<&M> for ErrorDetail {
+    fn from(message: &M) -> Self {
+        Self::from_message(message)
+    }
+}
+
+impl From<prost_types::Any> for ErrorDetail {
+    fn from(any: prost_types::Any) -> Self {
+        let type_name = match any.type_url.rsplit_once('/') {
+            Some((_, type_name)) => type_name.to_string(),
+            None => any.type_url,
+        };
+
+        Self::new(type_name, any.value)
+    }
+}
+
+impl From<ErrorDetail> for prost_types::Any {
+    fn from(detail: ErrorDetail) -> Self {
+        Self {
+            type_url: format!("{TYPE_URL_PREFIX}{}", detail.type_name),
+            value: detail.value,
+        }
+    }
+}
+
+fn serialize_base64<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD_NO_PAD.encode(value))
+}
+
+fn deserialize_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+
+    STANDARD_INDIFFERENT_PAD
+        .decode(value.as_bytes())
+        .map_err(serde::de::Error::custom)
+}
+
+// The standard error details from https://github.com/googleapis/googleapis/blob/master/google/rpc/error_details.proto
+
+/// Why an error happened, as a machine-readable `reason` within a `domain`
+#[derive(Clone, PartialEq, Message)]
+pub struct ErrorInfo {
+    #[prost(string, tag = "1")]
+    pub reason: String,
+    #[prost(string, tag = "2")]
+    pub domain: String,
+    #[prost(map = "string, string", tag = "3")]
+    pub metadata: HashMap<String, String>,
+}
+
+/// How long the client should wait before retrying
+#[derive(Clone, PartialEq, Message)]
+pub struct RetryInfo {
+    #[prost(message, optional, tag = "1")]
+    pub retry_delay: Option<prost_types::Duration>,
+}
+
+/// Debugging information from the server, like a stack trace
+#[derive(Clone, PartialEq, Message)]
+pub struct DebugInfo {
+    #[prost(string, repeated, tag = "1")]
+    pub stack_entries: Vec<String>,
+    #[prost(string, tag = "2")]
+    pub detail: String,
+}
+
+/// Which quotas were exceeded
+#[derive(Clone, PartialEq, Message)]
+pub struct QuotaFailure {
+    #[prost(message, repeated, tag = "1")]
+    pub violations: Vec<quota_failure::Violation>,
+}
+
+pub mod quota_failure {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Violation {
+        #[prost(string, tag = "1")]
+        pub subject: String,
+        #[prost(string, tag = "2")]
+        pub description: String,
+    }
+}
+
+/// Which preconditions of the request weren't met
+#[derive(Clone, PartialEq, Message)]
+pub struct PreconditionFailure {
+    #[prost(message, repeated, tag = "1")]
+    pub violations: Vec<precondition_failure::Violation>,
+}
+
+pub mod precondition_failure {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Violation {
+        #[prost(string, tag = "1")]
+        pub r#type: String,
+        #[prost(string, tag = "2")]
+        pub subject: String,
+        #[prost(string, tag = "3")]
+        pub description: String,
+    }
+}
+
+/// Which fields of the request were invalid, and why
+#[derive(Clone, PartialEq, Message)]
+pub struct BadRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub field_violations: Vec<bad_request::FieldViolation>,
+}
+
+pub mod bad_request {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FieldViolation {
+        #[prost(string, tag = "1")]
+        pub field: String,
+        #[prost(string, tag = "2")]
+        pub description: String,
+        #[prost(string, tag = "3")]
+        pub reason: String,
+        #[prost(message, optional, tag = "4")]
+        pub localized_message: Option<super::LocalizedMessage>,
+    }
+}
+
+/// Identifies the request, for example when filing a bug report
+#[derive(Clone, PartialEq, Message)]
+pub struct RequestInfo {
+    #[prost(string, tag = "1")]
+    pub request_id: String,
+    #[prost(string, tag = "2")]
+    pub serving_data: String,
+}
+
+/// The resource the error is about
+#[derive(Clone, PartialEq, Message)]
+pub struct ResourceInfo {
+    #[prost(string, tag = "1")]
+    pub resource_type: String,
+    #[prost(string, tag = "2")]
+    pub resource_name: String,
+    #[prost(string, tag = "3")]
+    pub owner: String,
+    #[prost(string, tag = "4")]
+    pub description: String,
+}
+
+/// Links to documentation that may help with the error
+#[derive(Clone, PartialEq, Message)]
+pub struct Help {
+    #[prost(message, repeated, tag = "1")]
+    pub links: Vec<help::Link>,
+}
+
+pub mod help {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Link {
+        #[prost(string, tag = "1")]
+        pub description: String,
+        #[prost(string, tag = "2")]
+        pub url: String,
+    }
+}
+
+/// An error message localized for the user
+#[derive(Clone, PartialEq, Message)]
+pub struct LocalizedMessage {
+    #[prost(string, tag = "1")]
+    pub locale: String,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+macro_rules! google_rpc_names {
+    ($($message:ident),* $(,)?) => {
+        $(
+            impl Name for $message {
+                const NAME: &'static str = stringify!($message);
+                const PACKAGE: &'static str = "google.rpc";
+            }
+        )*
+    };
+}
+
+google_rpc_names!(
+    ErrorInfo,
+    RetryInfo,
+    DebugInfo,
+    QuotaFailure,
+    PreconditionFailure,
+    BadRequest,
+    RequestInfo,
+    ResourceInfo,
+    Help,
+    LocalizedMessage,
+);
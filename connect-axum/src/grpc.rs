@@ -0,0 +1,622 @@
+//! The gRPC and gRPC-Web protocols, served alongside Connect by the same router: every call
+//! (unary or not) is a stream of enveloped messages, and its status is sent in trailers.
+//!
+//! https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+//! https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-WEB.md
+use std::convert::Infallible;
+use std::future::Future;
+
+use crate::compression::Compression;
+use crate::encode::{content_type, message_envelope};
+use crate::envelope::Envelope;
+use crate::{
+    Code, ConnectError, ConnectMessageProto, ConnectStreamRequest, Encoding, MessageLimits,
+    ResponseStream, RouterOptions,
+};
+use axum::body::Body;
+use axum::extract::Request;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use bytes::{Bytes, BytesMut};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use http::header::CONTENT_TYPE;
+use http::request::Parts;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use http_body::Frame;
+use http_body_util::StreamBody;
+use prost::Message;
+
+const GRPC_ACCEPT_ENCODING: &str = "grpc-accept-encoding";
+const GRPC_ENCODING: &str = "grpc-encoding";
+const GRPC_MESSAGE: &str = "grpc-message";
+const GRPC_STATUS: &str = "grpc-status";
+const GRPC_STATUS_DETAILS_BIN: &str = "grpc-status-details-bin";
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+
+/// The protocol allows at most 8 digits (plus a unit) in `grpc-timeout`
+const MAX_TIMEOUT_DIGITS: usize = 8;
+
+/// gRPC-Web sends its trailers in a final envelope with this flag set
+pub const FLAG_TRAILERS: u8 = 0b1000_0000;
+
+/// The flavors of gRPC, which differ in how they're framed on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// Trailers are HTTP trailers, which need HTTP/2
+    Grpc,
+    /// Trailers are sent in a final envelope, so any version of HTTP works
+    Web,
+    /// Like `Web`, with the whole body base64-encoded
+    WebText,
+}
+
+impl Variant {
+    /// The variant and codec name a `Content-Type` like `application/grpc-web+proto` is for
+    /// (`proto` if it doesn't name one)
+    pub fn from_content_type(content_type: &str) -> Option<(Self, &str)> {
+        let subtype = content_type.strip_prefix("application/")?;
+
+        // Longest prefix first, as each is a prefix of the next
+        let (variant, rest) = [
+            (Variant::WebText, "grpc-web-text"),
+            (Variant::Web, "grpc-web"),
+            (Variant::Grpc, "grpc"),
+        ]
+        .into_iter()
+        .find_map(|(variant, prefix)| Some((variant, subtype.strip_prefix(prefix)?)))?;
+
+        let codec = rest.split(';').next().unwrap_or_default().trim();
+
+        match codec {
+            "" => Some((variant, "proto")),
+            // Some other variant's prefix, like `application/grpc-foo`
+            _ => Some((variant, codec.strip_prefix('+')?)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Variant::Grpc => "grpc",
+            Variant::Web => "grpc-web",
+            Variant::WebText => "grpc-web-text",
+        }
+    }
+}
+
+/// Whether a request speaks gRPC or gRPC-Web rather than Connect, going by its content type
+pub fn is_grpc_request(req: &Request) -> bool {
+    request_variant(req.headers()).is_some()
+}
+
+fn request_variant(headers: &HeaderMap) -> Option<(Variant, &str)> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(Variant::from_content_type)
+}
+
+/// Serve a gRPC or gRPC-Web call: parse the request, hand it to `call` and encode the response
+/// it returns. Errors are always reported in trailers, never as HTTP errors.
+pub async fn handle<T, F, Fut>(req: Request, options: &RouterOptions, call: F) -> Response
+where
+    T: ConnectMessageProto,
+    F: FnOnce(ConnectStreamRequest) -> Fut,
+    Fut: Future<Output = crate::Response<ResponseStream<T>>>,
+{
+    // Errors in the request keep its variant and codec, when they're known
+    let (variant, encoding) = match request_variant(req.headers()) {
+        Some((variant, name)) => (variant, options.encoding(name, req.uri().path())),
+        None => (Variant::Grpc, None),
+    };
+
+    let grpc_req = match parse_grpc_request(req, options) {
+        Ok(grpc_req) => grpc_req,
+        Err(e) => {
+            let encoding = encoding.unwrap_or_else(Encoding::proto);
+            return encode_grpc_error(e, variant, &encoding);
+        }
+    };
+
+    let encoding = grpc_req.encoding.clone();
+    let compression = grpc_req.response_compression;
+    let limits = grpc_req.limits;
+
+    encode_grpc_response(call(grpc_req).await, variant, encoding, compression, limits)
+}
+
+/// Parse the headers of a gRPC or gRPC-Web request, leaving its enveloped messages in the body
+/// (decoded from base64 as they arrive, for gRPC-Web-text)
+pub fn parse_grpc_request(
+    req: Request,
+    options: &RouterOptions,
+) -> Result<ConnectStreamRequest, ConnectError> {
+    let (
+        Parts {
+            method,
+            headers,
+            uri,
+            version,
+            extensions,
+            ..
+        },
+        body,
+    ) = req.into_parts();
+
+    if method != Method::POST {
+        return Err(ConnectError::new(
+            Code::Unimplemented,
+            format!("Unsupported HTTP method for gRPC: {method}"),
+        ));
+    }
+
+    let request_encoding = request_variant(&headers)
+        .and_then(|(variant, name)| Some((variant, options.encoding(name, uri.path())?)));
+
+    let (variant, encoding) = match request_encoding {
+        Some((variant, encoding)) => (variant, encoding),
+        None => {
+            let content_type = headers
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+
+            return Err(ConnectError::new(
+                Code::Unimplemented,
+                format!("Unsupported gRPC content type: {content_type}"),
+            ));
+        }
+    };
+
+    let timeout_ms = parse_timeout(&headers)?;
+
+    let request_compression = match headers.get(GRPC_ENCODING) {
+        Some(value) => Compression::from_name(value.to_str().unwrap_or_default())?,
+        None => None,
+    };
+
+    let response_compression = headers
+        .get_all(GRPC_ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find_map(Compression::negotiate);
+
+    let body = match variant {
+        Variant::WebText => decode_base64_body(body),
+        Variant::Grpc | Variant::Web => body,
+    };
+
+    Ok(ConnectStreamRequest {
+        body,
+        headers,
+        extensions,
+        encoding,
+        limits: options.limits_for(uri.path()),
+        version,
+        timeout_ms,
+        protocol_version: None,
+        request_compression,
+        response_compression,
+    })
+}
+
+/// Encode a stream of response messages as enveloped frames, followed by trailers with the
+/// status of the call (and the response's own trailers)
+pub fn encode_grpc_response<T>(
+    response: crate::Response<ResponseStream<T>>,
+    variant: Variant,
+    encoding: Encoding,
+    compression: Option<Compression>,
+    limits: MessageLimits,
+) -> Response
+where
+    T: ConnectMessageProto,
+{
+    let crate::Response {
+        message: messages,
+        mut headers,
+        trailers,
+        extensions,
+    } = response;
+
+    headers.extend(response_headers(variant, &encoding, compression));
+
+    let frames = stream::unfold(Some((messages, trailers)), move |state| {
+        let encoding = encoding.clone();
+
+        async move {
+            let (mut messages, trailers) = state?;
+
+            let encoded = match messages.next().await {
+                Some(Ok(message)) => encoding.encode_response(&message),
+                Some(Err(e)) => Err(e),
+                None => {
+                    let trailers = status_trailers(None, trailers);
+                    return Some((trailers_frame(trailers, variant), None));
+                }
+            };
+
+            let frame = encoded
+                .and_then(|bytes| message_envelope(bytes, compression, limits))
+                .and_then(|envelope| envelope.encode());
+
+            match frame {
+                Ok(bytes) => Some((data_frame(bytes, variant), Some((messages, trailers)))),
+                Err(e) => {
+                    let trailers = status_trailers(Some(e), trailers);
+                    Some((trailers_frame(trailers, variant), None))
+                }
+            }
+        }
+    });
+
+    let body = Body::new(StreamBody::new(frames.map(Ok::<_, Infallible>)));
+
+    (StatusCode::OK, headers, extensions, body).into_response()
+}
+
+/// A "trailers-only" response, for calls that fail before they produce any messages
+pub fn encode_grpc_error(error: ConnectError, variant: Variant, encoding: &Encoding) -> Response {
+    let mut headers = response_headers(variant, encoding, None);
+    headers.extend(status_trailers(Some(error), HeaderMap::new()));
+
+    (StatusCode::OK, headers).into_response()
+}
+
+fn response_headers(
+    variant: Variant,
+    encoding: &Encoding,
+    compression: Option<Compression>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::from_iter([(
+        CONTENT_TYPE,
+        content_type(&format!("{}+", variant.name()), encoding),
+    )]);
+
+    if let Some(compression) = compression {
+        headers.insert(
+            HeaderName::from_static(GRPC_ENCODING),
+            HeaderValue::from_static(compression.name()),
+        );
+    }
+
+    headers.insert(
+        HeaderName::from_static(GRPC_ACCEPT_ENCODING),
+        HeaderValue::from_str(&crate::compression::accept_encoding())
+            .unwrap_or(HeaderValue::from_static("identity")),
+    );
+
+    headers
+}
+
+fn data_frame(bytes: Bytes, variant: Variant) -> Frame<Bytes> {
+    match variant {
+        Variant::Grpc | Variant::Web => Frame::data(bytes),
+        Variant::WebText => Frame::data(STANDARD.encode(bytes).into()),
+    }
+}
+
+/// HTTP trailers for gRPC, or a final envelope of HTTP/1-style header lines for gRPC-Web
+fn trailers_frame(trailers: HeaderMap, variant: Variant) -> Frame<Bytes> {
+    if variant == Variant::Grpc {
+        return Frame::trailers(trailers);
+    }
+
+    let mut payload = BytesMut::new();
+
+    for (name, value) in &trailers {
+        payload.extend_from_slice(name.as_str().as_bytes());
+        payload.extend_from_slice(b": ");
+        payload.extend_from_slice(value.as_bytes());
+        payload.extend_from_slice(b"\r\n");
+    }
+
+    // Header lines never come anywhere near the 4 GiB an envelope can hold
+    let envelope = Envelope::new(FLAG_TRAILERS, payload).encode();
+
+    data_frame(envelope.unwrap_or_default(), variant)
+}
+
+/// Decode a base64 body as it arrives. Clients may send it in separately padded chunks,
+/// so each padded quantum ends a chunk.
+fn decode_base64_body(body: Body) -> Body {
+    let mut buffer = BytesMut::new();
+
+    let decoded = body
+        .into_data_stream()
+        .map_err(|e| ConnectError::internal(format!("Failed to read body: {e}")))
+        .map_ok(Some)
+        // A final `None` marks the end of the body, which mustn't leave a partial quantum
+        .chain(stream::once(std::future::ready(Ok(None))))
+        .and_then(move |chunk| {
+            let result = match chunk {
+                Some(chunk) => decode_base64_quanta(&mut buffer, &chunk),
+                None if buffer.is_empty() => Ok(Vec::new()),
+                None => Err(ConnectError::invalid_argument(format!(
+                    "Base64 decode failed: {} trailing characters",
+                    buffer.len()
+                ))),
+            };
+
+            std::future::ready(result.map(Bytes::from))
+        });
+
+    Body::from_stream(decoded)
+}
+
+/// Add `chunk` to `buffer` and decode its whole quanta, leaving the rest for the next chunk
+fn decode_base64_quanta(buffer: &mut BytesMut, chunk: &[u8]) -> Result<Vec<u8>, ConnectError> {
+    buffer.extend(chunk.iter().filter(|b| !b.is_ascii_whitespace()));
+
+    // Only whole quanta of 4 characters can be decoded
+    let complete = buffer.split_to(buffer.len() / 4 * 4);
+
+    let mut decoded = Vec::with_capacity(complete.len() / 4 * 3);
+    complete
+        .split_inclusive(|b| *b == b'=')
+        .filter(|chunk| chunk != b"=")
+        .try_for_each(|chunk| {
+            // Each chunk runs up to its first padding character, so take the rest of it
+            let padding = (4 - chunk.len() % 4) % 4;
+            let mut padded = chunk.to_vec();
+            padded.resize(chunk.len() + padding, b'=');
+            STANDARD.decode_vec(&padded, &mut decoded)
+        })
+        .map_err(|e| ConnectError::invalid_argument(format!("Base64 decode failed: {e}")))?;
+
+    Ok(decoded)
+}
+
+/// `grpc-status`, `grpc-message` and `grpc-status-details-bin`, plus any other metadata
+pub(crate) fn status_trailers(
+    mut error: Option<ConnectError>,
+    mut trailers: HeaderMap,
+) -> HeaderMap {
+    let Some(error) = error.as_mut() else {
+        trailers.insert(GRPC_STATUS, HeaderValue::from_static("0"));
+        return trailers;
+    };
+
+    trailers.extend(std::mem::take(&mut *error.metadata));
+
+    let code = i32::from(error.code);
+    trailers.insert(GRPC_STATUS, HeaderValue::from(code));
+
+    if let Ok(message) = HeaderValue::try_from(percent_encode(&error.message)) {
+        trailers.insert(GRPC_MESSAGE, message);
+    }
+
+    if !error.details.is_empty() {
+        let status = Status {
+            code,
+            message: error.message.clone(),
+            details: error.details.iter().cloned().map(Into::into).collect(),
+        };
+
+        if let Ok(details) = HeaderValue::try_from(STANDARD_NO_PAD.encode(status.encode_to_vec())) {
+            trailers.insert(GRPC_STATUS_DETAILS_BIN, details);
+        }
+    }
+
+    trailers
+}
+
+/// `grpc-message` is percent-encoded, leaving printable ASCII (other than `%`) as is
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+
+    for byte in message.bytes() {
+        if (b' '..=b'~').contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    encoded
+}
+
+/// Parse `grpc-timeout`: up to 8 digits followed by a unit, rounded up to whole milliseconds
+fn parse_timeout(headers: &HeaderMap) -> Result<Option<u64>, ConnectError> {
+    let Some(timeout) = headers.get(GRPC_TIMEOUT) else {
+        return Ok(None);
+    };
+
+    let invalid = || ConnectError::invalid_argument("Invalid grpc-timeout value");
+
+    let timeout = timeout.to_str().map_err(|_| invalid())?;
+    let (value, unit) = timeout
+        .split_at_checked(timeout.len().saturating_sub(1))
+        .ok_or_else(invalid)?;
+
+    if value.is_empty()
+        || value.len() > MAX_TIMEOUT_DIGITS
+        || !value.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    let value: u64 = value.parse().map_err(|_| invalid())?;
+
+    let timeout_ms = match unit {
+        "H" => value * 60 * 60 * 1000,
+        "M" => value * 60 * 1000,
+        "S" => value * 1000,
+        "m" => value,
+        "u" => value.div_ceil(1000),
+        "n" => value.div_ceil(1_000_000),
+        _ => return Err(invalid()),
+    };
+
+    Ok(Some(timeout_ms))
+}
+
+/// `google.rpc.Status`, sent base64-encoded in `grpc-status-details-bin`
+#[derive(Clone, PartialEq, Message)]
+struct Status {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<prost_types::Any>,
+}
+
+// https://grpc.github.io/grpc/core/md_doc_statuscodes.html
+impl From<Code> for i32 {
+    fn from(code: Code) -> Self {
+        match code {
+            Code::Canceled => 1,
+            Code::Unknown => 2,
+            Code::InvalidArgument => 3,
+            Code::DeadlineExceeded => 4,
+            Code::NotFound => 5,
+            Code::AlreadyExists => 6,
+            Code::PermissionDenied => 7,
+            Code::ResourceExhausted => 8,
+            Code::FailedPrecondition => 9,
+            Code::Aborted => 10,
+            Code::OutOfRange => 11,
+            Code::Unimplemented => 12,
+            Code::Internal => 13,
+            Code::Unavailable => 14,
+            Code::DataLoss => 15,
+            Code::Unauthenticated => 16,
+        }
+    }
+}
+
+// https://grpc.github.io/grpc/core/md_doc_statuscodes.html
+impl TryFrom<i32> for Code {
+    type Error = ConnectError;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(Code::Canceled),
+            2 => Ok(Code::Unknown),
+            3 => Ok(Code::InvalidArgument),
+            4 => Ok(Code::DeadlineExceeded),
+            5 => Ok(Code::NotFound),
+            6 => Ok(Code::AlreadyExists),
+            7 => Ok(Code::PermissionDenied),
+            8 => Ok(Code::ResourceExhausted),
+            9 => Ok(Code::FailedPrecondition),
+            10 => Ok(Code::Aborted),
+            11 => Ok(Code::OutOfRange),
+            12 => Ok(Code::Unimplemented),
+            13 => Ok(Code::Internal),
+            14 => Ok(Code::Unavailable),
+            15 => Ok(Code::DataLoss),
+            16 => Ok(Code::Unauthenticated),
+            other => Err(ConnectError::internal(format!(
+                "Unknown gRPC status code: {other}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    fn timeout(value: &'static str) -> Result<Option<u64>, ConnectError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(GRPC_TIMEOUT, HeaderValue::from_static(value));
+        parse_timeout(&headers)
+    }
+
+    #[test]
+    fn no_timeout() {
+        assert_eq!(parse_timeout(&HeaderMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn timeout_units() {
+        assert_eq!(timeout("2H").unwrap(), Some(2 * 60 * 60 * 1000));
+        assert_eq!(timeout("3M").unwrap(), Some(3 * 60 * 1000));
+        assert_eq!(timeout("4S").unwrap(), Some(4000));
+        assert_eq!(timeout("5m").unwrap(), Some(5));
+    }
+
+    #[test]
+    fn timeout_rounded_up_to_milliseconds() {
+        assert_eq!(timeout("1u").unwrap(), Some(1));
+        assert_eq!(timeout("1000u").unwrap(), Some(1));
+        assert_eq!(timeout("1001u").unwrap(), Some(2));
+        assert_eq!(timeout("1n").unwrap(), Some(1));
+        assert_eq!(timeout("0n").unwrap(), Some(0));
+    }
+
+    #[test]
+    fn timeout_has_at_most_8_digits() {
+        assert_eq!(
+            timeout("99999999H").unwrap(),
+            Some(99_999_999 * 60 * 60 * 1000)
+        );
+        assert!(timeout("100000000m").is_err());
+    }
+
+    #[test]
+    fn invalid_timeouts() {
+        for value in ["", "S", "10", "10s", "-1S", "1.5S", " 1S", "1 S"] {
+            let error = timeout(value).unwrap_err();
+            assert_eq!(error.code(), Code::InvalidArgument, "{value:?}");
+        }
+    }
+
+    /// The decoded bytes of a base64 body arriving in `chunks`
+    async fn decode_base64(chunks: &[&'static str]) -> Result<Vec<u8>, String> {
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| Ok::<_, Infallible>(Bytes::from_static(chunk.as_bytes())))
+            .collect();
+        let body = decode_base64_body(Body::from_stream(stream::iter(chunks)));
+
+        body.collect()
+            .await
+            .map(|collected| collected.to_bytes().to_vec())
+            .map_err(|e| e.to_string())
+    }
+
+    #[tokio::test]
+    async fn base64_split_within_quanta() {
+        assert_eq!(
+            decode_base64(&["QU", "JD", "REVG"]).await.unwrap(),
+            b"ABCDEF"
+        );
+        assert_eq!(
+            decode_base64(&["Q", "UJ", "DRA", "=="]).await.unwrap(),
+            b"ABCD"
+        );
+    }
+
+    #[tokio::test]
+    async fn base64_padded_chunks() {
+        // Each frame encoded on its own, so padded in the middle of the body
+        assert_eq!(decode_base64(&["QQ==", "QkI="]).await.unwrap(), b"ABB");
+        assert_eq!(decode_base64(&["QQ==QkI=", "Qw=="]).await.unwrap(), b"ABBC");
+        assert_eq!(decode_base64(&["QQ=", "=QkI", "="]).await.unwrap(), b"ABB");
+    }
+
+    #[tokio::test]
+    async fn base64_ignores_whitespace() {
+        assert_eq!(
+            decode_base64(&["QUJD\r\n", " REVG"]).await.unwrap(),
+            b"ABCDEF"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_base64() {
+        assert!(decode_base64(&["QU*D"]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn base64_ending_in_a_partial_quantum() {
+        assert!(decode_base64(&["QUJD", "RE"]).await.is_err());
+        assert!(decode_base64(&["Q"]).await.is_err());
+        assert!(decode_base64(&["QQ==", "Q"]).await.is_err());
+        assert_eq!(decode_base64(&[]).await.unwrap(), b"");
+    }
+}
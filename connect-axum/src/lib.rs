@@ -1,41 +1,108 @@
+pub mod client;
+pub mod codec;
+pub mod compression;
+pub mod deadline;
 pub mod encode;
+pub mod envelope;
+pub mod error_details;
+pub mod grpc;
 pub mod message;
+pub mod options;
 pub mod parse;
+pub mod protocol_error;
+#[cfg(feature = "reflect")]
+pub mod reflect;
+pub mod request;
+pub mod response;
+pub mod stream;
+pub mod transport;
 
-pub use encode::encode_http_response;
-pub use parse::parse_connect_request;
+pub use codec::{Codec, Encoding};
+pub use compression::Compression;
+pub use encode::{encode_http_response, encode_stream_response};
+pub use error_details::ErrorDetail;
+pub use message::Empty;
+pub use options::{MessageLimits, RouterOptions};
+pub use parse::{parse_connect_request, parse_connect_stream_request};
+pub use protocol_error::ProtocolError;
+pub use request::Request;
+pub use response::Response;
+pub use stream::{RequestStream, ResponseStream};
 
 pub use connect_axum_macros::connect_rs_impl;
 
+/// Include the code connect-build generated for a package (like `"todos.v1"`) in `OUT_DIR`
+#[macro_export]
+macro_rules! include_proto {
+    ($package:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/", $package, ".connect.rs"));
+    };
+}
+
+/// The descriptors the code generator embeds next to each service, kept only with the `reflect`
+/// feature (the only one reading them) so other builds don't carry their bytes
+#[cfg(feature = "reflect")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! file_descriptor_set {
+    ($bytes:literal) => {
+        $bytes
+    };
+}
+
+#[cfg(not(feature = "reflect"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! file_descriptor_set {
+    ($bytes:literal) => {
+        &[]
+    };
+}
+
+const CONNECT_ACCEPT_ENCODING: &str = "connect-accept-encoding";
+const CONNECT_CONTENT_ENCODING: &str = "connect-content-encoding";
 const CONNECT_PROTOCOL_VERSION: &str = "connect-protocol-version";
 const CONNECT_TIMEOUT_MS: &str = "connect-timeout-ms";
 
-const APPLICATION_CONNECT_JSON: &str = "application/connect+json";
-const APPLICATION_CONNECT_PROTO: &str = "application/connect+proto";
-const APPLICATION_PROTO: &str = "application/proto";
+/// The only version of the protocol there is (so far)
+const PROTOCOL_VERSION: &str = "1";
 
 pub struct ConnectRequest {
     pub message: Vec<u8>,
+    pub headers: http::HeaderMap,
+    pub extensions: http::Extensions,
     pub encoding: Encoding,
+    pub limits: MessageLimits,
     pub timeout_ms: Option<u64>,
     pub protocol_version: Option<String>,
+    pub response_compression: Option<Compression>,
 }
 
-pub struct ConnectResponse {
-    pub message: Vec<u8>,
+pub struct ConnectStreamRequest {
+    pub body: axum::body::Body,
+    pub headers: http::HeaderMap,
+    pub extensions: http::Extensions,
     pub encoding: Encoding,
+    pub limits: MessageLimits,
+    pub version: http::Version,
+    pub timeout_ms: Option<u64>,
+    pub protocol_version: Option<String>,
+    pub request_compression: Option<Compression>,
+    pub response_compression: Option<Compression>,
 }
 
-#[derive(Clone)]
-pub enum Encoding {
-    Json,
-    Proto,
+pub struct ConnectResponse {
+    pub message: Vec<u8>,
+    pub encoding: Encoding,
 }
 
 #[derive(Debug)]
 pub struct ConnectError {
     code: Code,
     message: String,
+    details: Vec<ErrorDetail>,
+    // Boxed to keep `Result<_, ConnectError>` small
+    metadata: Box<http::HeaderMap>,
 }
 
 // https://connectrpc.com/docs/protocol/#error-codes
@@ -64,6 +131,8 @@ impl ConnectError {
         Self {
             code,
             message: message.into(),
+            details: Vec::new(),
+            metadata: Box::default(),
         }
     }
 
@@ -76,8 +145,62 @@ impl ConnectError {
     }
 
     // TODO: other constructors
+
+    /// Attach a detail, like `error.with_detail(&BadRequest { .. })`
+    pub fn with_detail(mut self, detail: impl Into<ErrorDetail>) -> Self {
+        self.details.push(detail.into());
+        self
+    }
+
+    /// Attach metadata, sent as headers of a unary response or in the end-stream message of a
+    /// streaming response, like `error.with_metadata(RETRY_AFTER, HeaderValue::from_static("30"))`
+    pub fn with_metadata(
+        mut self,
+        name: impl http::header::IntoHeaderName,
+        value: http::HeaderValue,
+    ) -> Self {
+        self.metadata.append(name, value);
+        self
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn details(&self) -> &[ErrorDetail] {
+        &self.details
+    }
+
+    pub fn metadata(&self) -> &http::HeaderMap {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut http::HeaderMap {
+        &mut self.metadata
+    }
+
+    /// The first detail of type `M`, like `error.detail::<RetryInfo>()`.
+    /// Details that fail to decode are skipped.
+    pub fn detail<M: prost::Name + Default>(&self) -> Option<M> {
+        self.details
+            .iter()
+            .find_map(|detail| detail.to_message().and_then(Result::ok))
+    }
 }
 
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code: &'static str = self.code.into();
+        write!(f, "{code}: {}", self.message)
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
 pub trait ConnectMessageProto: Send + Sync + 'static {
     fn encode_proto(&self) -> Result<Vec<u8>, ConnectError>;
     fn decode_proto(bytes: &[u8]) -> Result<Self, ConnectError>
@@ -85,9 +208,44 @@ pub trait ConnectMessageProto: Send + Sync + 'static {
         Self: Sized;
 }
 
+/// Messages that serde can serialize, for codecs other than Protobuf (like JSON)
+pub trait ConnectMessageSerde: Send + Sync + 'static {
+    fn as_serialize(&self) -> &dyn erased_serde::Serialize;
+    fn from_deserializer(
+        deserializer: &mut dyn erased_serde::Deserializer<'_>,
+    ) -> Result<Self, erased_serde::Error>
+    where
+        Self: Sized;
+}
+
+/// Messages that can be encoded as JSON, from before codecs were pluggable
+#[deprecated(note = "implement `ConnectMessageSerde`, which every codec other than Protobuf uses")]
 pub trait ConnectMessageJson: Send + Sync + 'static {
     fn encode_json(&self) -> Result<Vec<u8>, ConnectError>;
     fn decode_json(bytes: &[u8]) -> Result<Self, ConnectError>
     where
         Self: Sized;
 }
+
+#[allow(deprecated)]
+impl<T: ConnectMessageSerde> ConnectMessageJson for T {
+    fn encode_json(&self) -> Result<Vec<u8>, ConnectError> {
+        serde_json::to_vec(self.as_serialize())
+            .map_err(|e| ConnectError::internal(format!("JSON encode failed: {e}")))
+    }
+
+    fn decode_json(bytes: &[u8]) -> Result<Self, ConnectError> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            ConnectError::invalid_argument(format!("Invalid JSON: {e}"))
+        };
+
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        let message = T::from_deserializer(&mut <dyn erased_serde::Deserializer>::erase(
+            &mut deserializer,
+        ))
+        .map_err(|e| invalid(&e))?;
+
+        deserializer.end().map_err(|e| invalid(&e))?;
+        Ok(message)
+    }
+}
@@ -1,6 +1,9 @@
-use crate::ConnectError;
+use std::any::Any;
+use std::marker::PhantomData;
+
+use crate::{ConnectError, ConnectMessageSerde};
 use prost::Message;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 impl<T> crate::ConnectMessageProto for T
 where
@@ -16,17 +19,166 @@ where
     }
 }
 
-impl<T> crate::ConnectMessageJson for T
+impl<T> crate::ConnectMessageSerde for T
 where
-    T: Message + Default + Serialize + DeserializeOwned + Send + Sync + 'static,
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
 {
-    fn encode_json(&self) -> Result<Vec<u8>, ConnectError> {
-        serde_json::to_vec(self)
-            .map_err(|e| ConnectError::internal(format!("JSON encode failed: {e}")))
+    fn as_serialize(&self) -> &dyn erased_serde::Serialize {
+        self
+    }
+
+    fn from_deserializer(
+        deserializer: &mut dyn erased_serde::Deserializer<'_>,
+    ) -> Result<Self, erased_serde::Error> {
+        erased_serde::deserialize(deserializer)
+    }
+}
+
+/// The serde impls of a message type, for the codecs that need them (like JSON). Messages
+/// without any (like those with fields of well-known types) can still be sent as Protobuf, or
+/// as canonical JSON through their descriptors.
+///
+/// The generated routers and clients find them with [`serde_impls!`](crate::serde_impls).
+#[derive(Clone, Copy)]
+pub struct SerdeImpls {
+    serialize: for<'a> fn(&'a dyn Any) -> Option<&'a dyn erased_serde::Serialize>,
+    deserialize: DeserializeFn,
+}
+
+type DeserializeFn =
+    fn(&mut dyn erased_serde::Deserializer<'_>) -> Result<Box<dyn Any>, erased_serde::Error>;
+
+impl SerdeImpls {
+    pub fn of<T: ConnectMessageSerde>() -> Self {
+        Self {
+            serialize: |message| message.downcast_ref::<T>().map(T::as_serialize),
+            deserialize: |deserializer| {
+                T::from_deserializer(deserializer).map(|message| Box::new(message) as Box<dyn Any>)
+            },
+        }
+    }
+
+    /// `message` as a serde `Serialize`, if it's of the type these impls are for
+    pub(crate) fn serialize<'a>(
+        &self,
+        message: &'a dyn Any,
+    ) -> Option<&'a dyn erased_serde::Serialize> {
+        (self.serialize)(message)
+    }
+
+    pub(crate) fn deserialize<T: 'static>(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer<'_>,
+    ) -> Result<T, erased_serde::Error> {
+        let message = (self.deserialize)(deserializer)?;
+
+        message.downcast().map(|message| *message).map_err(|_| {
+            serde::de::Error::custom(format!(
+                "Deserialized another type than {}",
+                std::any::type_name::<T>()
+            ))
+        })
+    }
+}
+
+/// The [`SerdeImpls`] of a message type, or `None` if it has no serde impls
+///
+/// ```ignore
+/// let request = connect_axum::serde_impls!(GetTodoRequest);
+/// ```
+#[macro_export]
+macro_rules! serde_impls {
+    ($ty:ty) => {{
+        #[allow(unused_imports)]
+        use $crate::message::{NoSerdeImpls as _, WithSerdeImpls as _};
+
+        // Method resolution picks the impl for `&SerdeProbe<T>` when `T` has serde impls,
+        // and falls back to the one for `SerdeProbe<T>` otherwise
+        (&&$crate::message::SerdeProbe::<$ty>::new()).serde_impls()
+    }};
+}
+
+#[doc(hidden)]
+pub struct SerdeProbe<T>(PhantomData<T>);
+
+impl<T> SerdeProbe<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait WithSerdeImpls {
+    fn serde_impls(&self) -> Option<SerdeImpls>;
+}
+
+impl<T: ConnectMessageSerde> WithSerdeImpls for &SerdeProbe<T> {
+    fn serde_impls(&self) -> Option<SerdeImpls> {
+        Some(SerdeImpls::of::<T>())
+    }
+}
+
+#[doc(hidden)]
+pub trait NoSerdeImpls {
+    fn serde_impls(&self) -> Option<SerdeImpls>;
+}
+
+impl<T> NoSerdeImpls for SerdeProbe<T> {
+    fn serde_impls(&self) -> Option<SerdeImpls> {
+        None
     }
+}
+
+/// `google.protobuf.Empty`, for methods taking or returning nothing: encoded as nothing in
+/// Protobuf and as `{}` in JSON, which the code generator maps it to (prost-types' has no serde
+/// impls)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Message, Serialize, Deserialize)]
+pub struct Empty {}
+
+impl prost::Name for Empty {
+    const NAME: &'static str = "Empty";
+    const PACKAGE: &'static str = "google.protobuf";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct WithSerde {
+        id: String,
+    }
+
+    struct WithoutSerde;
+
+    #[test]
+    fn finds_serde_impls() {
+        let impls = serde_impls!(WithSerde).unwrap();
+        let message = WithSerde { id: "a".into() };
+
+        let serialize = impls.serialize(&message).unwrap();
+        assert_eq!(serde_json::to_string(serialize).unwrap(), r#"{"id":"a"}"#);
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#"{"id":"b"}"#);
+        let message: WithSerde = impls
+            .deserialize(&mut <dyn erased_serde::Deserializer>::erase(
+                &mut deserializer,
+            ))
+            .unwrap();
+        assert_eq!(message.id, "b");
+    }
+
+    #[test]
+    fn finds_no_serde_impls() {
+        assert!(serde_impls!(WithoutSerde).is_none());
+        assert!(serde_impls!(prost_types::Timestamp).is_none());
+    }
+
+    #[test]
+    fn serializes_only_the_type_they_are_for() {
+        let impls = SerdeImpls::of::<WithSerde>();
 
-    fn decode_json(bytes: &[u8]) -> Result<Self, ConnectError> {
-        serde_json::from_slice(bytes)
-            .map_err(|e| ConnectError::invalid_argument(format!("Invalid JSON: {e}")))
+        assert!(impls.serialize(&WithoutSerde).is_none());
     }
 }
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::codec::{Codec, JsonCodec, ProtoCodec};
+use crate::message::SerdeImpls;
+use crate::{Code, ConnectError, Encoding};
+
+/// Received messages are limited to 4 MiB by default, like gRPC's
+const DEFAULT_MAX_RECEIVE_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Options for the router generated by [`connect_rs_impl`](crate::connect_rs_impl),
+/// passed to its `into_router_with`
+#[derive(Clone)]
+pub struct RouterOptions {
+    pub(crate) strict: bool,
+    codecs: Vec<Arc<dyn Codec>>,
+    limits: MessageLimits,
+    method_limits: HashMap<String, MessageLimits>,
+    /// The serde impls of each method's request and response messages, by path
+    method_serde: HashMap<String, (Option<SerdeImpls>, Option<SerdeImpls>)>,
+    #[cfg(feature = "reflect")]
+    descriptors: Option<prost_reflect::DescriptorPool>,
+}
+
+impl Default for RouterOptions {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            codecs: vec![Arc::new(JsonCodec), Arc::new(ProtoCodec)],
+            limits: MessageLimits::default(),
+            method_limits: HashMap::new(),
+            method_serde: HashMap::new(),
+            #[cfg(feature = "reflect")]
+            descriptors: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for RouterOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let codecs: Vec<_> = self.codecs.iter().map(|codec| codec.name()).collect();
+
+        f.debug_struct("RouterOptions")
+            .field("strict", &self.strict)
+            .field("codecs", &codecs)
+            .field("limits", &self.limits)
+            .field("method_limits", &self.method_limits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RouterOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject Connect requests that don't declare the protocol version, as the spec allows:
+    /// `POST`s must send `Connect-Protocol-Version: 1`, and `GET`s the `connect=v1` parameter.
+    /// Catches misconfigured clients early, at the cost of rejecting hand-written requests
+    /// (from cURL, say) that leave them out. gRPC requests aren't affected.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Accept requests in another encoding, for content types ending in the codec's name
+    /// (`application/msgpack`, `application/connect+msgpack`, `application/grpc+msgpack`...).
+    /// Replaces any codec of the same name, including the built-in `json` and `proto`.
+    pub fn codec(mut self, codec: impl Codec) -> Self {
+        let codec: Arc<dyn Codec> = Arc::new(codec);
+
+        match self.codecs.iter_mut().find(|c| c.name() == codec.name()) {
+            Some(existing) => *existing = codec,
+            None => self.codecs.push(codec),
+        }
+
+        self
+    }
+
+    /// Limit the size of the messages of every method
+    pub fn limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Limit the size of the messages of the method served at `path`, like
+    /// `/todos.v1.TodosService/AddTodos` (the `path` of its generated `MethodMeta`), instead of
+    /// the router's limits
+    pub fn method_limits(mut self, path: impl Into<String>, limits: MessageLimits) -> Self {
+        self.method_limits.insert(path.into(), limits);
+        self
+    }
+
+    /// Encode and decode JSON messages in the canonical Protobuf mapping, through the
+    /// descriptors embedded by the code generator, instead of with the messages' serde impls.
+    /// Requests may use either camelCase or the original field names, and unknown fields are
+    /// ignored.
+    #[cfg(feature = "reflect")]
+    pub fn canonical_json(self, canonical_json: bool) -> Self {
+        if canonical_json {
+            self.codec(crate::reflect::CanonicalJsonCodec)
+        } else {
+            self.codec(JsonCodec)
+        }
+    }
+
+    /// Provide the encoded `FileDescriptorSet` of the service, which the generated router does
+    /// with the one embedded by the code generator. Fails if it doesn't decode to a valid set of
+    /// descriptors.
+    #[doc(hidden)]
+    #[cfg_attr(not(feature = "reflect"), allow(unused_variables))]
+    pub fn with_file_descriptor_set(
+        self,
+        file_descriptor_set: &[u8],
+    ) -> Result<Self, ConnectError> {
+        #[cfg(feature = "reflect")]
+        {
+            let descriptors = prost_reflect::DescriptorPool::decode(file_descriptor_set)
+                .map_err(|e| ConnectError::internal(format!("Invalid file descriptor set: {e}")))?;
+
+            Ok(Self {
+                descriptors: Some(descriptors),
+                ..self
+            })
+        }
+
+        #[cfg(not(feature = "reflect"))]
+        Ok(self)
+    }
+
+    /// Provide the serde impls of the request and response messages of the method served at
+    /// `path`, which the generated router does for each of its methods
+    #[doc(hidden)]
+    pub fn with_serde_impls(
+        mut self,
+        path: impl Into<String>,
+        request: Option<SerdeImpls>,
+        response: Option<SerdeImpls>,
+    ) -> Self {
+        self.method_serde.insert(path.into(), (request, response));
+        self
+    }
+
+    /// The encoding of messages named `name` (like `json`) for the method served at `path`,
+    /// if there's a codec for it
+    pub(crate) fn encoding(&self, name: &str, path: &str) -> Option<Encoding> {
+        let codec = self.codecs.iter().find(|codec| codec.name() == name)?;
+        let (request_serde, response_serde) =
+            self.method_serde.get(path).copied().unwrap_or_default();
+        let encoding = Encoding::new(codec.clone()).with_serde(request_serde, response_serde);
+
+        #[cfg(feature = "reflect")]
+        let encoding = encoding.with_method(
+            self.descriptors
+                .as_ref()
+                .and_then(|descriptors| crate::reflect::find_method(descriptors, path)),
+        );
+
+        Some(encoding)
+    }
+
+    /// The limits of the method served at `path`
+    pub(crate) fn limits_for(&self, path: &str) -> MessageLimits {
+        self.method_limits.get(path).copied().unwrap_or(self.limits)
+    }
+
+    /// The content types a router accepts, for `Accept-Post`: the Connect ones (each codec's
+    /// name prefixed by `connect_prefix`), then the gRPC and gRPC-Web ones
+    pub(crate) fn accept_post(&self, connect_prefix: &str) -> String {
+        let names = || self.codecs.iter().map(|codec| codec.name());
+
+        let mut content_types: Vec<_> = names()
+            .map(|name| format!("application/{connect_prefix}{name}"))
+            .collect();
+
+        for variant in ["grpc", "grpc-web", "grpc-web-text"] {
+            content_types.push(format!("application/{variant}"));
+            content_types.extend(names().map(|name| format!("application/{variant}+{name}")));
+        }
+
+        content_types.join(", ")
+    }
+}
+
+/// The largest messages a method receives and sends, in bytes: 4 MiB and unlimited by default.
+/// Received messages are limited both as sent and once decompressed, and exceeding either
+/// limit fails the call with `resource_exhausted`.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageLimits {
+    max_receive_message_size: usize,
+    max_send_message_size: usize,
+}
+
+impl Default for MessageLimits {
+    fn default() -> Self {
+        Self {
+            max_receive_message_size: DEFAULT_MAX_RECEIVE_MESSAGE_SIZE,
+            max_send_message_size: usize::MAX,
+        }
+    }
+}
+
+impl MessageLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_receive_message_size(mut self, bytes: usize) -> Self {
+        self.max_receive_message_size = bytes;
+        self
+    }
+
+    pub fn max_send_message_size(mut self, bytes: usize) -> Self {
+        self.max_send_message_size = bytes;
+        self
+    }
+
+    pub(crate) fn max_receive(&self) -> usize {
+        self.max_receive_message_size
+    }
+
+    pub(crate) fn check_receive(&self, size: usize) -> Result<(), ConnectError> {
+        check(size, self.max_receive_message_size, "Received")
+    }
+
+    pub(crate) fn check_send(&self, size: usize) -> Result<(), ConnectError> {
+        check(size, self.max_send_message_size, "Sent")
+    }
+}
+
+fn check(size: usize, max: usize, direction: &str) -> Result<(), ConnectError> {
+    if size <= max {
+        return Ok(());
+    }
+
+    Err(ConnectError::new(
+        Code::ResourceExhausted,
+        format!("{direction} message of {size} bytes exceeds the limit of {max} bytes"),
+    ))
+}
+
+#[cfg(all(test, feature = "reflect"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_file_descriptor_sets_are_errors() {
+        let error = RouterOptions::new()
+            .with_file_descriptor_set(b"\xff")
+            .unwrap_err();
+
+        assert_eq!(error.code(), Code::Internal);
+    }
+}
@@ -1,58 +1,172 @@
 use std::collections::HashMap;
 
+use crate::compression::Compression;
+use crate::deadline::MAX_TIMEOUT_DIGITS;
+use crate::encode::content_type;
 use crate::{
-    APPLICATION_CONNECT_JSON, APPLICATION_CONNECT_PROTO, APPLICATION_PROTO,
-    CONNECT_PROTOCOL_VERSION, CONNECT_TIMEOUT_MS, Code, ConnectError, ConnectRequest, Encoding,
+    CONNECT_ACCEPT_ENCODING, CONNECT_CONTENT_ENCODING, CONNECT_PROTOCOL_VERSION,
+    CONNECT_TIMEOUT_MS, Code, ConnectError, ConnectRequest, ConnectStreamRequest, Encoding,
+    MessageLimits, PROTOCOL_VERSION, ProtocolError, RouterOptions,
 };
 use axum::body::Body;
 use axum::extract::Request;
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use http::header::CONTENT_TYPE;
+use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
 use http::request::Parts;
 use http::{HeaderMap, Method};
-use http_body_util::BodyExt;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
 use url::form_urlencoded;
 
-pub async fn parse_connect_request(req: Request) -> Result<ConnectRequest, ConnectError> {
+pub async fn parse_connect_request(
+    req: Request,
+    options: &RouterOptions,
+) -> Result<ConnectRequest, ProtocolError> {
     let (
         Parts {
             method,
             headers,
             uri,
+            extensions,
             ..
         },
         body,
     ) = req.into_parts();
 
+    let limits = options.limits_for(uri.path());
+
     let (encoding, message) = match method {
-        Method::POST => extract_from_post_request(&headers, body).await?,
-        Method::GET => extract_from_get_request(&uri)?,
+        Method::POST => {
+            if options.strict {
+                require_protocol_version(&headers)?;
+            }
+
+            extract_from_post_request(&headers, uri.path(), body, options, limits).await?
+        }
+        Method::GET => extract_from_get_request(&uri, options, limits)?,
         _ => {
-            return Err(ConnectError::new(
-                Code::InvalidArgument,
-                format!("Unsupported HTTP method: {}", method),
-            ));
+            return Err(ProtocolError::MethodNotAllowed {
+                method,
+                allow: "GET, POST",
+            });
         }
     };
 
     let timeout_ms = parse_timeout(&headers)?;
     let protocol_version = parse_protocol_version(&headers);
+    let response_compression = negotiate_compression(&headers, ACCEPT_ENCODING.as_str());
 
     Ok(ConnectRequest {
         message,
+        headers,
+        extensions,
         encoding,
+        limits,
         timeout_ms,
         protocol_version,
+        response_compression,
     })
 }
 
+/// Parse the headers of a streaming request, leaving its enveloped messages in the body
+/// to be decoded as they arrive (see [`RequestStream`](crate::RequestStream))
+pub fn parse_connect_stream_request(
+    req: Request,
+    options: &RouterOptions,
+) -> Result<ConnectStreamRequest, ProtocolError> {
+    let (
+        Parts {
+            method,
+            headers,
+            uri,
+            version,
+            extensions,
+            ..
+        },
+        body,
+    ) = req.into_parts();
+
+    if method != Method::POST {
+        return Err(ProtocolError::MethodNotAllowed {
+            method,
+            allow: "POST",
+        });
+    }
+
+    let encoding = parse_stream_content_type(&headers, uri.path(), options)?;
+
+    // From here on, errors are reported in an end-stream message, like those of the stream
+    let stream_error = |error| ProtocolError::StreamRpc {
+        error,
+        content_type: content_type("connect+", &encoding),
+    };
+
+    if options.strict {
+        require_protocol_version(&headers).map_err(stream_error)?;
+    }
+
+    let timeout_ms = parse_timeout(&headers).map_err(stream_error)?;
+    let protocol_version = parse_protocol_version(&headers);
+    let request_compression =
+        parse_compression(&headers, CONNECT_CONTENT_ENCODING).map_err(stream_error)?;
+    let response_compression = negotiate_compression(&headers, CONNECT_ACCEPT_ENCODING);
+
+    Ok(ConnectStreamRequest {
+        body,
+        headers,
+        extensions,
+        encoding,
+        limits: options.limits_for(uri.path()),
+        version,
+        timeout_ms,
+        protocol_version,
+        request_compression,
+        response_compression,
+    })
+}
+
+fn parse_stream_content_type(
+    headers: &HeaderMap,
+    path: &str,
+    options: &RouterOptions,
+) -> Result<Encoding, ProtocolError> {
+    let media_type = media_type(headers);
+
+    // Streaming requests use `application/connect+{codec}`
+    media_type
+        .as_deref()
+        .and_then(|media_type| media_type.strip_prefix("application/connect+"))
+        .and_then(|name| options.encoding(name, path))
+        .ok_or_else(|| ProtocolError::UnsupportedMediaType {
+            content_type: media_type,
+            accept_post: options.accept_post("connect+"),
+        })
+}
+
+/// The `Content-Type` without any parameters (like `charset=utf-8`), lowercased
+fn media_type(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    let media_type = content_type.split(';').next().unwrap_or_default();
+
+    Some(media_type.trim().to_ascii_lowercase())
+}
+
 fn parse_timeout(headers: &HeaderMap) -> Result<Option<u64>, ConnectError> {
     if let Some(timeout_header) = headers.get(CONNECT_TIMEOUT_MS) {
         let timeout_str = timeout_header
             .to_str()
             .map_err(|_| ConnectError::invalid_argument("Invalid timeout header"))?;
 
+        if timeout_str.is_empty() || !timeout_str.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ConnectError::invalid_argument("Invalid timeout value"));
+        }
+
+        if timeout_str.len() > MAX_TIMEOUT_DIGITS {
+            return Err(ConnectError::invalid_argument(format!(
+                "Timeout too long: {CONNECT_TIMEOUT_MS} allows at most {MAX_TIMEOUT_DIGITS} digits"
+            )));
+        }
+
         let timeout = timeout_str
             .parse::<u64>()
             .map_err(|_| ConnectError::invalid_argument("Invalid timeout value"))?;
@@ -63,6 +177,39 @@ fn parse_timeout(headers: &HeaderMap) -> Result<Option<u64>, ConnectError> {
     }
 }
 
+/// The compression applied to the request, from a `Content-Encoding`-style header
+fn parse_compression(
+    headers: &HeaderMap,
+    header: &str,
+) -> Result<Option<Compression>, ConnectError> {
+    match headers.get(header) {
+        Some(value) => Compression::from_name(value.to_str().unwrap_or_default()),
+        None => Ok(None),
+    }
+}
+
+/// The compression to apply to the response, from an `Accept-Encoding`-style header
+fn negotiate_compression(headers: &HeaderMap, header: &str) -> Option<Compression> {
+    headers
+        .get_all(header)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find_map(Compression::negotiate)
+}
+
+/// Strict mode requires `POST`s to declare the (only) version of the protocol
+fn require_protocol_version(headers: &HeaderMap) -> Result<(), ConnectError> {
+    match parse_protocol_version(headers) {
+        Some(version) if version == PROTOCOL_VERSION => Ok(()),
+        Some(version) => Err(ConnectError::invalid_argument(format!(
+            "Unsupported {CONNECT_PROTOCOL_VERSION}: {version} (expected {PROTOCOL_VERSION})"
+        ))),
+        None => Err(ConnectError::invalid_argument(format!(
+            "Missing required header: set {CONNECT_PROTOCOL_VERSION} to {PROTOCOL_VERSION}"
+        ))),
+    }
+}
+
 fn parse_protocol_version(headers: &HeaderMap) -> Option<String> {
     headers
         .get(CONNECT_PROTOCOL_VERSION)
@@ -72,52 +219,62 @@ fn parse_protocol_version(headers: &HeaderMap) -> Option<String> {
 
 async fn extract_from_post_request(
     headers: &HeaderMap,
+    path: &str,
     body: Body,
-) -> Result<(Encoding, Vec<u8>), ConnectError> {
-    let encoding = if let Some(header) = headers.get(CONTENT_TYPE) {
-        if let Ok(content_type) = header.to_str() {
-            if content_type.starts_with(mime::APPLICATION_JSON.as_ref())
-                || content_type.starts_with(APPLICATION_CONNECT_JSON)
-            {
-                Encoding::Json
-            } else if content_type.starts_with(APPLICATION_PROTO)
-                || content_type.starts_with(APPLICATION_CONNECT_PROTO)
-            {
-                Encoding::Proto
-            } else {
-                return Err(ConnectError::invalid_argument(format!(
-                    "Unsupported content type: {content_type}"
-                )));
-            }
-        } else {
-            return Err(ConnectError::invalid_argument(format!(
-                "Invalid content type header: {:?}",
-                header,
-            )));
-        }
-    } else {
-        // MAYBE: don't default to JSON
-        Encoding::Json
-    };
+    options: &RouterOptions,
+    limits: MessageLimits,
+) -> Result<(Encoding, Vec<u8>), ProtocolError> {
+    let media_type = media_type(headers);
 
-    let message = body
+    // Unary requests use `application/{codec}`, without the `connect+` prefix of streaming
+    // requests
+    let encoding = media_type
+        .as_deref()
+        .and_then(|media_type| media_type.strip_prefix("application/"))
+        .and_then(|name| options.encoding(name, path))
+        .ok_or_else(|| ProtocolError::UnsupportedMediaType {
+            content_type: media_type,
+            accept_post: options.accept_post(""),
+        })?;
+
+    // Stop reading as soon as the body outgrows the limit, rather than buffering all of it
+    let message = Limited::new(body, limits.max_receive())
         .collect()
         .await
-        .map_err(|e| ConnectError::internal(format!("Failed to read request body: {e}")))?
+        .map_err(|e| match e.downcast::<LengthLimitError>() {
+            Ok(_) => ConnectError::new(
+                Code::ResourceExhausted,
+                format!(
+                    "Received message exceeds the limit of {} bytes",
+                    limits.max_receive()
+                ),
+            ),
+            Err(e) => ConnectError::internal(format!("Failed to read request body: {e}")),
+        })?
         .to_bytes()
         .to_vec();
 
+    let message = match parse_compression(headers, CONTENT_ENCODING.as_str())? {
+        Some(compression) => compression.decompress_limited(&message, limits.max_receive())?,
+        None => message,
+    };
+
     Ok((encoding, message))
 }
 
 /// Extract encoding and message from query parameters for GET requests
-/// Format: ?encoding=json&message=<encoded>&base64=1&connect=v1
-fn extract_from_get_request(uri: &http::Uri) -> Result<(Encoding, Vec<u8>), ConnectError> {
+/// Format: ?encoding=json&message=<encoded>&base64=1&compression=gzip&connect=v1
+fn extract_from_get_request(
+    uri: &http::Uri,
+    options: &RouterOptions,
+    limits: MessageLimits,
+) -> Result<(Encoding, Vec<u8>), ConnectError> {
     const BASE_64: &str = "base64";
+    const COMPRESSION: &str = "compression";
+    const CONNECT: &str = "connect";
+    const CONNECT_V1: &str = "v1";
     const ENCODING: &str = "encoding";
-    const JSON: &str = "json";
     const MESSAGE: &str = "message";
-    const PROTO: &str = "proto";
 
     let query = uri
         .query()
@@ -127,20 +284,30 @@ fn extract_from_get_request(uri: &http::Uri) -> Result<(Encoding, Vec<u8>), Conn
         .into_owned()
         .collect();
 
+    // Strict mode requires GETs to declare the (only) version of the protocol
+    if options.strict {
+        match params.get(CONNECT) {
+            Some(version) if version == CONNECT_V1 => {}
+            Some(version) => {
+                return Err(ConnectError::invalid_argument(format!(
+                    "Unsupported '{CONNECT}' parameter: {version} (expected {CONNECT_V1})"
+                )));
+            }
+            None => {
+                return Err(ConnectError::invalid_argument(format!(
+                    "GET request missing '{CONNECT}' parameter: set it to {CONNECT_V1}"
+                )));
+            }
+        }
+    }
+
     let encoding = params.get(ENCODING).ok_or_else(|| {
         ConnectError::invalid_argument("GET request missing 'encoding' parameter")
     })?;
 
-    let encoding = match encoding.as_str() {
-        JSON => Encoding::Json,
-        PROTO => Encoding::Proto,
-        other => {
-            return Err(ConnectError::invalid_argument(format!(
-                "Unsupported encoding: {}",
-                other
-            )));
-        }
-    };
+    let encoding = options.encoding(encoding, uri.path()).ok_or_else(|| {
+        ConnectError::invalid_argument(format!("Unsupported encoding: {encoding}"))
+    })?;
 
     let message_encoded = params
         .get(MESSAGE)
@@ -154,6 +321,18 @@ fn extract_from_get_request(uri: &http::Uri) -> Result<(Encoding, Vec<u8>), Conn
         urlencoding::decode_binary(message_encoded.as_bytes()).into_owned()
     };
 
+    let compression = match params.get(COMPRESSION) {
+        Some(name) => Compression::from_name(name)?,
+        None => None,
+    };
+
+    limits.check_receive(message.len())?;
+
+    let message = match compression {
+        Some(compression) => compression.decompress_limited(&message, limits.max_receive())?,
+        None => message,
+    };
+
     Ok((encoding, message))
 }
 
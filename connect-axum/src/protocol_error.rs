@@ -0,0 +1,88 @@
+//! Errors in how a request is made at the HTTP level, which the protocol reports with plain
+//! HTTP status codes rather than as RPC errors (except on streaming endpoints, once the content
+//! type is known).
+//!
+//! https://connectrpc.com/docs/protocol/#unary-request
+use crate::{ConnectError, encode};
+use axum::response::{IntoResponse, Response};
+use http::header::{ALLOW, HeaderName};
+use http::{HeaderValue, Method, StatusCode};
+
+const ACCEPT_POST: HeaderName = HeaderName::from_static("accept-post");
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The request's `Content-Type` isn't one the endpoint accepts: HTTP 415, listing the
+    /// content types it does accept in `Accept-Post`
+    UnsupportedMediaType {
+        content_type: Option<String>,
+        accept_post: String,
+    },
+    /// The endpoint doesn't accept the request's HTTP method: HTTP 405, listing the methods
+    /// it does accept in `Allow`
+    MethodNotAllowed { method: Method, allow: &'static str },
+    /// The request is well-formed HTTP, but an invalid RPC
+    Rpc(ConnectError),
+    /// The request is an invalid RPC on a streaming endpoint, which the protocol reports like
+    /// the errors of the stream itself: in the end-stream message of an HTTP 200 response, of
+    /// the request's content type
+    StreamRpc {
+        error: ConnectError,
+        content_type: HeaderValue,
+    },
+}
+
+impl From<ConnectError> for ProtocolError {
+    fn from(error: ConnectError) -> Self {
+        Self::Rpc(error)
+    }
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedMediaType {
+                content_type: Some(content_type),
+                ..
+            } => write!(f, "Unsupported content type: {content_type}"),
+            Self::UnsupportedMediaType {
+                content_type: None, ..
+            } => write!(f, "Missing content type"),
+            Self::MethodNotAllowed { method, .. } => {
+                write!(f, "Unsupported HTTP method: {method}")
+            }
+            Self::Rpc(error) | Self::StreamRpc { error, .. } => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl IntoResponse for ProtocolError {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+
+        match self {
+            Self::UnsupportedMediaType { accept_post, .. } => {
+                let mut response = (StatusCode::UNSUPPORTED_MEDIA_TYPE, message).into_response();
+
+                if let Ok(accept_post) = HeaderValue::try_from(accept_post) {
+                    response.headers_mut().insert(ACCEPT_POST, accept_post);
+                }
+
+                response
+            }
+            Self::MethodNotAllowed { allow, .. } => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                [(ALLOW, HeaderValue::from_static(allow))],
+                message,
+            )
+                .into_response(),
+            Self::Rpc(error) => error.into_response(),
+            Self::StreamRpc {
+                error,
+                content_type,
+            } => encode::end_stream_error_response(error, content_type),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! The canonical Protobuf JSON mapping, transcoded through the messages' descriptors rather than
+//! their serde derives, so well-known types, 64-bit integers, enums and `oneof`s all come out
+//! the way every other Protobuf implementation expects.
+//!
+//! https://protobuf.dev/programming-guides/json/
+use crate::ConnectError;
+use crate::codec::{Codec, DecodeMessage, EncodeMessage};
+use prost::Message;
+use prost_reflect::{
+    DescriptorPool, DeserializeOptions, DynamicMessage, MethodDescriptor, SerializeOptions,
+};
+
+pub use prost_reflect;
+
+/// Look up the method served at `path` (`/package.Service/Method`)
+pub(crate) fn find_method(pool: &DescriptorPool, path: &str) -> Option<MethodDescriptor> {
+    let (service, method) = path.trim_start_matches('/').split_once('/')?;
+
+    pool.get_service_by_name(service)?
+        .methods()
+        .find(|m| m.name() == method)
+}
+
+/// The canonical Protobuf JSON mapping, as `json`: encodes camelCase field names, 64-bit
+/// integers as strings, enums by name and leaves default values out. Decodes both camelCase and
+/// original field names, ignoring unknown fields.
+///
+/// Needs the descriptors the code generator embeds, so it only works for routers generated by
+/// [`connect_rs_impl`](crate::connect_rs_impl)
+pub struct CanonicalJsonCodec;
+
+impl Codec for CanonicalJsonCodec {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn uses_serde(&self) -> bool {
+        false
+    }
+
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, ConnectError> {
+        let descriptor = message
+            .descriptor()
+            .cloned()
+            .ok_or_else(missing_descriptor)?;
+
+        let dynamic = DynamicMessage::decode(descriptor, message.encode_proto()?.as_slice())
+            .map_err(|e| ConnectError::internal(format!("JSON encode failed: {e}")))?;
+
+        let mut serializer = serde_json::Serializer::new(Vec::new());
+        dynamic
+            .serialize_with_options(&mut serializer, &SerializeOptions::new())
+            .map_err(|e| ConnectError::internal(format!("JSON encode failed: {e}")))?;
+
+        Ok(serializer.into_inner())
+    }
+
+    fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), ConnectError> {
+        let descriptor = message
+            .descriptor()
+            .cloned()
+            .ok_or_else(missing_descriptor)?;
+
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        let options = DeserializeOptions::new().deny_unknown_fields(false);
+
+        let dynamic =
+            DynamicMessage::deserialize_with_options(descriptor, &mut deserializer, &options)
+                .and_then(|dynamic| deserializer.end().map(|()| dynamic))
+                .map_err(|e| ConnectError::invalid_argument(format!("Invalid JSON: {e}")))?;
+
+        message.decode_proto(&dynamic.encode_to_vec())
+    }
+}
+
+fn missing_descriptor() -> ConnectError {
+    ConnectError::internal("Canonical JSON needs the method's descriptors")
+}
@@ -0,0 +1,59 @@
+use http::{Extensions, HeaderMap};
+
+/// A request message along with the HTTP headers and extensions it arrived with.
+///
+/// Handlers can take a `Request<T>` instead of a bare `T` to read things like auth headers,
+/// or the peer address (as [`ConnectInfo`](axum::extract::ConnectInfo) in the extensions,
+/// when served with `into_make_service_with_connect_info`).
+#[derive(Debug)]
+pub struct Request<T> {
+    message: T,
+    headers: HeaderMap,
+    extensions: Extensions,
+}
+
+impl<T> Request<T> {
+    pub fn new(message: T) -> Self {
+        Self::from_parts(HeaderMap::new(), Extensions::new(), message)
+    }
+
+    pub fn from_parts(headers: HeaderMap, extensions: Extensions, message: T) -> Self {
+        Self {
+            message,
+            headers,
+            extensions,
+        }
+    }
+
+    pub fn into_parts(self) -> (HeaderMap, Extensions, T) {
+        (self.headers, self.extensions, self.message)
+    }
+
+    pub fn message(&self) -> &T {
+        &self.message
+    }
+
+    pub fn message_mut(&mut self) -> &mut T {
+        &mut self.message
+    }
+
+    pub fn into_message(self) -> T {
+        self.message
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+}
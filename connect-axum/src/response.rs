@@ -0,0 +1,80 @@
+use http::{Extensions, HeaderMap};
+
+/// A response message along with the HTTP headers, trailers and extensions to send with it.
+///
+/// Handlers can return a `Response<T>` instead of a bare `T` to set custom headers.
+/// Trailers are sent as `trailer-`-prefixed headers for unary RPCs, and as the metadata
+/// of the end-stream message for streaming ones.
+#[derive(Debug)]
+pub struct Response<T> {
+    pub(crate) message: T,
+    pub(crate) headers: HeaderMap,
+    pub(crate) trailers: HeaderMap,
+    pub(crate) extensions: Extensions,
+}
+
+impl<T> Response<T> {
+    pub fn new(message: T) -> Self {
+        Self {
+            message,
+            headers: HeaderMap::new(),
+            trailers: HeaderMap::new(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    pub fn message(&self) -> &T {
+        &self.message
+    }
+
+    pub fn message_mut(&mut self) -> &mut T {
+        &mut self.message
+    }
+
+    pub fn into_message(self) -> T {
+        self.message
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    pub fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+
+    pub fn trailers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.trailers
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Replace the message, keeping the headers, trailers and extensions
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Response<U> {
+        Response {
+            message: f(self.message),
+            headers: self.headers,
+            trailers: self.trailers,
+            extensions: self.extensions,
+        }
+    }
+
+    pub fn try_map<U, E>(self, f: impl FnOnce(T) -> Result<U, E>) -> Result<Response<U>, E> {
+        Ok(Response {
+            message: f(self.message)?,
+            headers: self.headers,
+            trailers: self.trailers,
+            extensions: self.extensions,
+        })
+    }
+}
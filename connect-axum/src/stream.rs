@@ -0,0 +1,254 @@
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+
+use crate::envelope::{Envelope, EnvelopeStream};
+use crate::{Code, Compression, ConnectError, ConnectMessageProto, Encoding, MessageLimits};
+use axum::BoxError;
+use axum::body::{Body, Bytes};
+use futures_util::{StreamExt, TryStream, stream};
+use http::HeaderMap;
+
+pub use futures_util::Stream;
+
+/// A stream of messages sent from the client to the server
+pub struct RequestStream<T> {
+    inner: Pin<Box<dyn Stream<Item = Result<T, ConnectError>> + Send>>,
+}
+
+impl<T> RequestStream<T> {
+    pub fn new(stream: impl Stream<Item = Result<T, ConnectError>> + Send + 'static) -> Self {
+        Self {
+            inner: Box::pin(stream),
+        }
+    }
+
+    /// Read every message of the request before returning, for transports that can't
+    /// interleave the request and response bodies (like HTTP/1.1)
+    pub async fn half_duplex(self) -> Self
+    where
+        T: Send + 'static,
+    {
+        let messages: Vec<_> = self.collect().await;
+
+        Self::new(stream::iter(messages))
+    }
+
+    /// Read the one and only message of the request
+    pub async fn into_message(self) -> Result<T, ConnectError> {
+        single_message(self, Code::InvalidArgument).await
+    }
+}
+
+impl<T> RequestStream<T>
+where
+    T: ConnectMessageProto,
+{
+    /// Decode an enveloped request body (as received by a server) into its messages as they
+    /// arrive, failing with `resource_exhausted` on any message over the receive limit
+    pub fn decode(
+        body: Body,
+        encoding: Encoding,
+        compression: Option<Compression>,
+        limits: MessageLimits,
+    ) -> Self {
+        let max_len = limits.max_receive();
+        let envelopes = EnvelopeStream::new(body).with_max_payload_len(max_len);
+
+        Self::new(envelopes.map(move |envelope| {
+            let envelope = envelope?;
+
+            if envelope.is_end_stream() {
+                return Err(ConnectError::invalid_argument(
+                    "Unexpected end-stream message in request",
+                ));
+            }
+
+            let payload = uncompressed_payload(envelope, compression, max_len)?;
+
+            encoding.decode_request(&payload)
+        }))
+    }
+}
+
+impl<T> Stream for RequestStream<T> {
+    type Item = Result<T, ConnectError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+/// A stream of messages sent from the server to the client
+pub struct ResponseStream<T> {
+    inner: Pin<Box<dyn Stream<Item = Result<T, ConnectError>> + Send>>,
+    trailers: Arc<OnceLock<HeaderMap>>,
+}
+
+impl<T> ResponseStream<T> {
+    pub fn new(stream: impl Stream<Item = Result<T, ConnectError>> + Send + 'static) -> Self {
+        Self {
+            inner: Box::pin(stream),
+            trailers: Arc::default(),
+        }
+    }
+
+    /// The trailers of a stream received by a client, from its end-stream message, once the
+    /// stream has ended
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.get()
+    }
+
+    /// Wrap the stream in another, which keeps its trailers
+    pub(crate) fn wrap<S>(self, wrap: impl FnOnce(Self) -> S) -> Self
+    where
+        S: Stream<Item = Result<T, ConnectError>> + Send + 'static,
+    {
+        let trailers = self.trailers.clone();
+
+        Self {
+            trailers,
+            ..Self::new(wrap(self))
+        }
+    }
+
+    /// A stream of exactly one message
+    pub fn from_message(message: T) -> Self
+    where
+        T: Send + 'static,
+    {
+        Self::new(stream::once(async move { Ok(message) }))
+    }
+
+    /// A stream that fails immediately, before yielding any messages
+    pub fn from_error(error: ConnectError) -> Self
+    where
+        T: Send + 'static,
+    {
+        Self::new(stream::once(async move { Err(error) }))
+    }
+
+    /// Read the one and only message of the response
+    pub async fn into_message(self) -> Result<T, ConnectError> {
+        single_message(self, Code::Unimplemented).await
+    }
+}
+
+impl<T> ResponseStream<T>
+where
+    T: ConnectMessageProto,
+{
+    /// Decode an enveloped response body (as received by a client) into its messages.
+    /// The stream ends after the end-stream message, yielding its error (if any) last.
+    pub fn decode<S>(body: S, encoding: Encoding, compression: Option<Compression>) -> Self
+    where
+        S: TryStream + Send + 'static,
+        S::Ok: Into<Bytes>,
+        S::Error: Into<BoxError>,
+    {
+        let envelopes = EnvelopeStream::new(Body::from_stream(body));
+        let trailers = Arc::<OnceLock<HeaderMap>>::default();
+        let received_trailers = trailers.clone();
+
+        let messages = Self::new(stream::unfold(Some(envelopes), move |state| {
+            let encoding = encoding.clone();
+            let trailers = received_trailers.clone();
+
+            async move {
+                let mut envelopes = state?;
+
+                let item = match envelopes.next().await {
+                    Some(Ok(envelope)) if envelope.is_end_stream() => {
+                        return match envelope.into_end_stream() {
+                            Ok(end_stream) => {
+                                let _ = trailers.set(end_stream.metadata.clone());
+
+                                end_stream.error.map(|mut e| {
+                                    e.metadata.extend(end_stream.metadata);
+                                    (Err(e), None)
+                                })
+                            }
+                            Err(e) => Some((Err(e), None)),
+                        };
+                    }
+                    Some(Ok(envelope)) => uncompressed_payload(envelope, compression, usize::MAX)
+                        .and_then(|payload| encoding.decode_response(&payload)),
+                    Some(Err(e)) => return Some((Err(e), None)),
+                    None => {
+                        return Some((
+                            Err(ConnectError::internal(
+                                "Stream ended without end-stream message",
+                            )),
+                            None,
+                        ));
+                    }
+                };
+
+                Some((item, Some(envelopes)))
+            }
+        }));
+
+        Self {
+            trailers,
+            ..messages
+        }
+    }
+}
+
+impl<T> Stream for ResponseStream<T> {
+    type Item = Result<T, ConnectError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+/// Encode a stream of messages as the enveloped body of a streaming request
+pub fn encode_messages<T>(
+    messages: impl Stream<Item = T> + Send + 'static,
+    encoding: Encoding,
+) -> impl Stream<Item = Result<Bytes, ConnectError>> + Send + 'static
+where
+    T: ConnectMessageProto,
+{
+    messages.map(move |message| {
+        let bytes = encoding.encode_request(&message)?;
+
+        Envelope::message(bytes).encode()
+    })
+}
+
+pub(crate) async fn single_message<T>(
+    mut messages: impl Stream<Item = Result<T, ConnectError>> + Unpin,
+    code: Code,
+) -> Result<T, ConnectError> {
+    let message = messages
+        .next()
+        .await
+        .ok_or_else(|| ConnectError::new(code, "Missing message"))??;
+
+    if messages.next().await.transpose()?.is_some() {
+        return Err(ConnectError::new(code, "Expected exactly one message"));
+    }
+
+    Ok(message)
+}
+
+fn uncompressed_payload(
+    envelope: Envelope,
+    compression: Option<Compression>,
+    max_len: usize,
+) -> Result<Bytes, ConnectError> {
+    if !envelope.is_compressed() {
+        return Ok(envelope.payload);
+    }
+
+    match compression {
+        Some(compression) => compression
+            .decompress_limited(&envelope.payload, max_len)
+            .map(Bytes::from),
+        None => Err(ConnectError::internal(
+            "Received a compressed message, but no compression was negotiated",
+        )),
+    }
+}
@@ -0,0 +1,137 @@
+//! Transports: how the generated clients send their requests. Any tower `Service` works (like a
+//! hyper client, or an axum `Router` for in-process calls), and reqwest does too behind the
+//! `reqwest` feature.
+use std::future::Future;
+
+use crate::{Code, ConnectError};
+use axum::BoxError;
+use axum::body::{Body, Bytes};
+use tower_service::Service;
+
+#[cfg(feature = "reqwest")]
+pub use reqwest;
+
+/// Sends a request and receives the response's head, leaving its body to be streamed
+pub trait Transport: Clone + Send + Sync + 'static {
+    fn send(
+        &self,
+        request: http::Request<Body>,
+    ) -> impl Future<Output = Result<http::Response<Body>, ConnectError>> + Send;
+}
+
+/// A transport over any tower `Service` taking HTTP requests
+#[derive(Debug, Clone)]
+pub struct ServiceTransport<S> {
+    service: S,
+}
+
+impl<S> ServiceTransport<S> {
+    pub fn new(service: S) -> Self {
+        Self { service }
+    }
+}
+
+impl<S, B> Transport for ServiceTransport<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<B>> + Clone + Send + Sync + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    B: http_body::Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    async fn send(
+        &self,
+        request: http::Request<Body>,
+    ) -> Result<http::Response<Body>, ConnectError> {
+        let mut service = self.service.clone();
+
+        std::future::poll_fn(|cx| service.poll_ready(cx))
+            .await
+            .map_err(unavailable)?;
+
+        let response = service.call(request).await.map_err(unavailable)?;
+
+        Ok(response.map(Body::new))
+    }
+}
+
+/// A transport over a hyper client, speaking HTTP/1.1 without TLS by default. For HTTP/2 or TLS,
+/// build the client yourself and pass it to [`ServiceTransport::new`].
+#[cfg(feature = "hyper")]
+pub type HyperTransport = ServiceTransport<
+    hyper_util::client::legacy::Client<hyper_util::client::legacy::connect::HttpConnector, Body>,
+>;
+
+#[cfg(feature = "hyper")]
+impl Default for HyperTransport {
+    fn default() -> Self {
+        let client =
+            hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+                .build_http();
+
+        Self::new(client)
+    }
+}
+
+/// A transport over a reqwest client
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Client> for ReqwestTransport {
+    fn from(client: reqwest::Client) -> Self {
+        Self::new(client)
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl Transport for ReqwestTransport {
+    async fn send(
+        &self,
+        request: http::Request<Body>,
+    ) -> Result<http::Response<Body>, ConnectError> {
+        use http_body::Body as _;
+        use http_body_util::BodyExt;
+
+        let (parts, body) = request.into_parts();
+
+        // Bodies of a known size (every one but those of client-streaming requests) are sent
+        // whole, so they get a `Content-Length`
+        let body = match body.size_hint().exact() {
+            Some(_) => reqwest::Body::from(body.collect().await.map_err(unavailable)?.to_bytes()),
+            None => reqwest::Body::wrap_stream(body.into_data_stream()),
+        };
+
+        let request = reqwest::Request::try_from(http::Request::from_parts(parts, body))
+            .map_err(|e| ConnectError::internal(format!("Invalid request: {e}")))?;
+
+        let response = self.client.execute(request).await.map_err(unavailable)?;
+
+        let mut http_response = http::Response::builder()
+            .status(response.status())
+            .version(response.version());
+
+        if let Some(headers) = http_response.headers_mut() {
+            *headers = response.headers().clone();
+        }
+
+        http_response
+            .body(Body::from_stream(response.bytes_stream()))
+            .map_err(|e| ConnectError::internal(format!("Invalid response: {e}")))
+    }
+}
+
+/// Failing to reach the server at all is worth retrying
+fn unavailable(error: impl Into<BoxError>) -> ConnectError {
+    ConnectError::new(Code::Unavailable, error.into().to_string())
+}
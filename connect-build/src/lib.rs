@@ -0,0 +1,244 @@
+//! Generating the messages, services and clients of `.proto` files from a build script, with
+//! [`prost_build`] for the messages and the same code generator as `protoc-gen-connect-rs-axum`
+//! for the rest, without buf or protoc plugins.
+//!
+//! ```rust,no_run
+//! // build.rs
+//! fn main() -> std::io::Result<()> {
+//!     connect_build::configure()
+//!         .serde(true)
+//!         .compile_protos(&["proto/todos/v1/todos.proto"], &["proto"])
+//! }
+//! ```
+//!
+//! The generated code lands in `OUT_DIR`, one file per package, for
+//! `connect_axum::include_proto!("todos.v1")`.
+use std::io;
+use std::path::{Path, PathBuf};
+
+use prost_types::compiler::CodeGeneratorRequest;
+
+pub use prost_build;
+pub use protoc_gen_connect_rs_axum::ClientTransport;
+use protoc_gen_connect_rs_axum::Options;
+
+/// Compile `proto`, importing from its directory, with the default options
+pub fn compile_protos(proto: impl AsRef<Path>) -> io::Result<()> {
+    let proto = proto.as_ref();
+    let include = proto.parent().unwrap_or(Path::new("."));
+
+    configure().compile_protos(&[proto], &[include])
+}
+
+pub fn configure() -> Builder {
+    Builder::default()
+}
+
+/// Options for the generated code, like the plugin's parameters
+#[derive(Debug, Clone)]
+pub struct Builder {
+    server: bool,
+    client: bool,
+    client_transport: ClientTransport,
+    serde: bool,
+    include_file: Option<String>,
+    extern_paths: Vec<(String, String)>,
+    connect_axum_path: Option<String>,
+    out_dir: Option<PathBuf>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            server: true,
+            client: true,
+            client_transport: ClientTransport::Reqwest,
+            serde: false,
+            include_file: None,
+            extern_paths: Vec::new(),
+            connect_axum_path: None,
+            out_dir: None,
+        }
+    }
+}
+
+impl Builder {
+    /// Generate the service traits (on by default)
+    pub fn server(mut self, server: bool) -> Self {
+        self.server = server;
+        self
+    }
+
+    /// Generate the clients (on by default)
+    pub fn client(mut self, client: bool) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// The transport the clients use by default (reqwest unless set)
+    pub fn client_transport(mut self, client_transport: ClientTransport) -> Self {
+        self.client_transport = client_transport;
+        self
+    }
+
+    /// Derive serde's `Serialize` and `Deserialize` for the messages (off by default), which
+    /// connect-axum's JSON codec (and any other going through serde) uses. Without them,
+    /// messages are sent as Protobuf, or as JSON with `RouterOptions::canonical_json`.
+    ///
+    /// The derives aren't the canonical JSON mapping (64-bit integers are numbers, bytes are
+    /// arrays, enums are integers), which connect-axum's `reflect` feature provides instead with
+    /// `RouterOptions::canonical_json`. Nor do they build for messages with fields of well-known
+    /// types, which have no serde impls in prost-types: map those to pbjson-types with
+    /// `extern_path` and derive through [`Builder::compile_protos_with_config`] instead.
+    pub fn serde(mut self, serde: bool) -> Self {
+        self.serde = serde;
+        self
+    }
+
+    /// Also generate a file named `include_file`, with a module for each package (like
+    /// `common::v1`) including its generated code
+    pub fn include_file(mut self, include_file: impl Into<String>) -> Self {
+        self.include_file = Some(include_file.into());
+        self
+    }
+
+    /// Map the messages of a Protobuf package or type to a Rust path instead of generating them,
+    /// like `.extern_path(".common.v1", "::common::v1")`
+    pub fn extern_path(
+        mut self,
+        proto_path: impl Into<String>,
+        rust_path: impl Into<String>,
+    ) -> Self {
+        self.extern_paths
+            .push((proto_path.into(), rust_path.into()));
+        self
+    }
+
+    /// The path to connect-axum in generated code, for crates re-exporting it
+    pub fn connect_axum_path(mut self, path: impl Into<String>) -> Self {
+        self.connect_axum_path = Some(path.into());
+        self
+    }
+
+    /// Where to write the generated code (`OUT_DIR` unless set)
+    pub fn out_dir(mut self, out_dir: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(out_dir.into());
+        self
+    }
+
+    /// Compile `protos`, importing from `includes`
+    pub fn compile_protos(
+        self,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+    ) -> io::Result<()> {
+        self.compile_protos_with_config(prost_build::Config::new(), protos, includes)
+    }
+
+    /// Compile `protos`, importing from `includes`, with the messages generated by `config`.
+    ///
+    /// Type names are enabled on `config`, so every message implements `prost::Name` and can be
+    /// attached to errors as a detail.
+    pub fn compile_protos_with_config(
+        self,
+        mut config: prost_build::Config,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+    ) -> io::Result<()> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => std::env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| io::Error::other("OUT_DIR environment variable is not set"))?,
+        };
+
+        for path in protos.iter().map(AsRef::as_ref) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+
+        for path in includes.iter().map(AsRef::as_ref) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+
+        // Parsed like the plugin's parameters, so they're checked and normalized the same way
+        let parameter = self.parameter();
+        let options = Options::parse(&parameter).map_err(io::Error::other)?;
+
+        config.out_dir(&out_dir);
+        // `prost::Name` for every message, so any of them can be an error detail
+        config.enable_type_names();
+
+        for (proto_path, rust_path) in &options.extern_paths {
+            config.extern_path(proto_path, rust_path);
+        }
+
+        if self.serde {
+            config.type_attribute(".", "#[derive(::serde::Serialize, ::serde::Deserialize)]");
+            // Not for enums, whose variants would be renamed
+            config.message_attribute(".", "#[serde(rename_all = \"camelCase\", default)]");
+        }
+
+        let file_descriptor_set = config.load_fds(protos, includes)?;
+        config.compile_fds(file_descriptor_set.clone())?;
+
+        // Every file, like prost: the generator skips those generated elsewhere
+        let request = CodeGeneratorRequest {
+            file_to_generate: file_descriptor_set
+                .file
+                .iter()
+                .map(|file| file.name().to_string())
+                .collect(),
+            parameter: Some(parameter),
+            proto_file: file_descriptor_set.file,
+            compiler_version: None,
+        };
+
+        let response = protoc_gen_connect_rs_axum::generate(&request);
+
+        if let Some(error) = response.error {
+            return Err(io::Error::other(error));
+        }
+
+        for file in response.file {
+            write_file_if_changed(&out_dir.join(file.name()), file.content().as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// The options as the plugin's parameters
+    fn parameter(&self) -> String {
+        let mut parameters = vec![
+            format!("server={}", self.server),
+            format!("client={}", self.client),
+        ];
+
+        parameters.push(match self.client_transport {
+            ClientTransport::Reqwest => "client_transport=reqwest".to_string(),
+            ClientTransport::Hyper => "client_transport=hyper".to_string(),
+        });
+
+        if let Some(include_file) = &self.include_file {
+            parameters.push(format!("include_file={include_file}"));
+        }
+
+        for (proto_path, rust_path) in &self.extern_paths {
+            parameters.push(format!("extern_path={proto_path}={rust_path}"));
+        }
+
+        if let Some(path) = &self.connect_axum_path {
+            parameters.push(format!("connect_axum_path={path}"));
+        }
+
+        parameters.join(",")
+    }
+}
+
+/// Write `content` to `path` unless it's already there, so cargo doesn't rebuild for nothing
+fn write_file_if_changed(path: &Path, content: &[u8]) -> io::Result<()> {
+    if std::fs::read(path).is_ok_and(|previous| previous == content) {
+        return Ok(());
+    }
+
+    std::fs::write(path, content)
+}
@@ -0,0 +1,25 @@
+use connect_build::prost_build;
+
+fn main() -> std::io::Result<()> {
+    connect_build::configure()
+        .serde(true)
+        .include_file("mod.rs")
+        .compile_protos_with_config(config()?, &["proto/todos/v1/todos.proto"], &["proto"])?;
+
+    // The default options, without serde impls, for the tests of messages that can't have them
+    // (with fields of well-known types)
+    connect_build::configure().compile_protos_with_config(
+        config()?,
+        &["proto/clock/v1/clock.proto"],
+        &["proto"],
+    )
+}
+
+/// A vendored protoc, so the example builds without installing one
+fn config() -> std::io::Result<prost_build::Config> {
+    let mut config = prost_build::Config::new();
+    config
+        .protoc_executable(protoc_bin_vendored::protoc_bin_path().map_err(std::io::Error::other)?);
+
+    Ok(config)
+}
@@ -1,9 +1,19 @@
-#[path = "../generated/todos.v1.connect.rs"]
-mod todos;
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/mod.rs"));
+}
+
+use std::time::Duration;
 
 use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use connect_axum::Code;
+use connect_axum::client::CallOptions;
+use connect_axum::error_details::BadRequest;
+use futures_util::{StreamExt, stream};
+use generated::todos::v1::{
+    AddTodosRequest, CompleteTodosRequest, GetTodoRequest, ListTodosRequest, Todo,
+    TodosServiceClient,
+};
 use reqwest::Client;
-use todos::{GetTodoRequest, TodosServiceClient};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -35,5 +45,112 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     }
 
+    println!("=== GET requests with generated client ===");
+    let get_todo_response = client
+        .clone()
+        .with_get_requests(true)
+        .get_todo(GetTodoRequest {
+            id: "get out of bed".to_string(),
+        })
+        .await
+        .expect("response error");
+
+    if let Some(todo) = get_todo_response.todo {
+        println!(r#"TODO: (id: "{}", task: "{}")"#, todo.id, todo.task);
+    }
+
+    println!("=== Errors with generated client ===");
+    let anonymous_client = TodosServiceClient::new("http://localhost:3000");
+
+    if let Err(e) = anonymous_client
+        .get_todo(GetTodoRequest {
+            id: "get out of bed".to_string(),
+        })
+        .await
+    {
+        match e.code() {
+            Code::Unauthenticated => println!("Not logged in: {}", e.message()),
+            _ => println!("Error: {e}"),
+        }
+    }
+
+    println!("=== Call options with generated client ===");
+    let response = anonymous_client
+        .get_todo_with(
+            GetTodoRequest {
+                id: "get out of bed".to_string(),
+            },
+            CallOptions::new()
+                .timeout(Duration::from_secs(5))
+                .header("token", HeaderValue::from_static("opensesame")),
+        )
+        .await
+        .expect("response error");
+
+    if let Some(remaining) = response.headers().get("x-todos-remaining") {
+        println!("TODOs remaining: {}", remaining.to_str()?);
+    }
+
+    println!("=== Streaming with generated client ===");
+    let mut todos = client
+        .list_todos(ListTodosRequest {})
+        .await
+        .expect("response error");
+
+    while let Some(response) = todos.next().await {
+        if let Some(todo) = response.expect("stream error").todo {
+            println!(r#"TODO: (id: "{}", task: "{}")"#, todo.id, todo.task);
+        }
+    }
+
+    println!("=== Client streaming with generated client ===");
+    let add_todos_response = client
+        .add_todos(stream::iter(["walk the dog", "water the plants"].map(
+            |task| AddTodosRequest {
+                todo: Some(Todo {
+                    id: task.to_string(),
+                    task: task.to_string(),
+                    done: false,
+                }),
+            },
+        )))
+        .await
+        .expect("response error");
+
+    println!("Added {} TODOs", add_todos_response.added);
+
+    println!("=== Bidi streaming with generated client ===");
+    let mut completed = client
+        .complete_todos(stream::iter(
+            ["walk the dog", "water the plants"]
+                .map(|id| CompleteTodosRequest { id: id.to_string() }),
+        ))
+        .await
+        .expect("response error");
+
+    while let Some(response) = completed.next().await {
+        if let Some(todo) = response.expect("stream error").todo {
+            println!(r#"Completed TODO: (id: "{}")"#, todo.id);
+        }
+    }
+
+    println!("=== Error details with generated client ===");
+    let mut completed = client
+        .complete_todos(stream::iter([CompleteTodosRequest { id: String::new() }]))
+        .await
+        .expect("response error");
+
+    if let Some(Err(e)) = completed.next().await {
+        println!("Error: {e}");
+
+        for violation in e
+            .detail::<BadRequest>()
+            .unwrap_or_default()
+            .field_violations
+        {
+            println!("  {}: {}", violation.field, violation.description);
+        }
+    }
+
     Ok(())
 }
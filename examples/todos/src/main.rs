@@ -1,27 +1,116 @@
-#[path = "./generated/todos.v1.connect.rs"]
-mod todos_v1;
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/mod.rs"));
+}
 
+use axum::http::HeaderValue;
 use tokio::net::TcpListener;
 
-use connect_axum::connect_rs_impl;
+use connect_axum::error_details::{BadRequest, bad_request::FieldViolation};
+use connect_axum::{
+    Code, ConnectError, Empty, Request, RequestStream, Response, ResponseStream, connect_rs_impl,
+};
+use futures_util::{StreamExt, stream};
 
-use todos_v1::{GetTodoRequest, GetTodoResponse, Todo, TodosService};
+use generated::todos::v1::{
+    AddTodosRequest, AddTodosResponse, CompleteTodosRequest, CompleteTodosResponse, GetTodoRequest,
+    GetTodoResponse, ListTodosRequest, ListTodosResponse, Todo, TodosService,
+};
 
 struct TodosServer;
 
-#[connect_rs_impl(todos_v1::TodosService)]
+#[connect_rs_impl(generated::todos::v1::TodosService)]
 impl TodosServer {
     async fn get_todo(
         &self,
-        req: GetTodoRequest,
-    ) -> Result<GetTodoResponse, connect_axum::ConnectError> {
-        Ok(GetTodoResponse {
+        req: Request<GetTodoRequest>,
+    ) -> Result<Response<GetTodoResponse>, connect_axum::ConnectError> {
+        if req.headers().get("token") != Some(&HeaderValue::from_static("opensesame")) {
+            return Err(ConnectError::new(Code::Unauthenticated, "Invalid token"));
+        }
+
+        let mut response = Response::new(GetTodoResponse {
             todo: Some(Todo {
                 id: "get out of bed".to_string(),
                 task: "Set the alarm, obey it, and be productive from the get-go".to_string(),
                 done: false,
             }),
-        })
+        });
+
+        response
+            .headers_mut()
+            .insert("x-todos-remaining", HeaderValue::from_static("1"));
+
+        Ok(response)
+    }
+
+    async fn list_todos(
+        &self,
+        _req: ListTodosRequest,
+    ) -> Result<ResponseStream<ListTodosResponse>, connect_axum::ConnectError> {
+        let todos = [
+            ("get out of bed", "Set the alarm and obey it", true),
+            ("make coffee", "Grind the beans the night before", false),
+        ];
+
+        Ok(ResponseStream::new(stream::iter(todos.map(
+            |(id, task, done)| {
+                Ok(ListTodosResponse {
+                    todo: Some(Todo {
+                        id: id.to_string(),
+                        task: task.to_string(),
+                        done,
+                    }),
+                })
+            },
+        ))))
+    }
+
+    async fn add_todos(
+        &self,
+        mut requests: RequestStream<AddTodosRequest>,
+    ) -> Result<AddTodosResponse, connect_axum::ConnectError> {
+        let mut added = 0;
+
+        while let Some(request) = requests.next().await {
+            request?;
+            added += 1;
+        }
+
+        Ok(AddTodosResponse { added })
+    }
+
+    async fn complete_todos(
+        &self,
+        requests: RequestStream<CompleteTodosRequest>,
+    ) -> Result<ResponseStream<CompleteTodosResponse>, connect_axum::ConnectError> {
+        Ok(ResponseStream::new(requests.map(|request| {
+            let id = request?.id;
+
+            if id.is_empty() {
+                return Err(
+                    ConnectError::invalid_argument("Missing TODO id").with_detail(&BadRequest {
+                        field_violations: vec![FieldViolation {
+                            field: "id".to_string(),
+                            description: "A TODO id is required".to_string(),
+                            ..Default::default()
+                        }],
+                    }),
+                );
+            }
+
+            Ok(CompleteTodosResponse {
+                todo: Some(Todo {
+                    id,
+                    task: String::new(),
+                    done: true,
+                }),
+            })
+        })))
+    }
+
+    async fn clear_completed(&self, _req: Empty) -> Result<Empty, connect_axum::ConnectError> {
+        // Nothing's kept, so there's nothing to forget
+        Ok(Empty {})
     }
 }
 
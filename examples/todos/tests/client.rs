@@ -0,0 +1,468 @@
+//! Generated clients calling the generated router, in-process through a `ServiceTransport` or
+//! over HTTP with hyper
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/mod.rs"));
+}
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{self, HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use futures_util::{StreamExt, stream};
+use prost::Message;
+
+use connect_axum::client::CallOptions;
+use connect_axum::error_details::ResourceInfo;
+use connect_axum::transport::{HyperTransport, ServiceTransport, Transport};
+use connect_axum::{
+    Code, ConnectError, Empty, Encoding, RequestStream, Response, ResponseStream, connect_rs_impl,
+};
+
+use generated::todos::v1::{
+    AddTodosRequest, AddTodosResponse, CompleteTodosRequest, CompleteTodosResponse, GetTodoRequest,
+    GetTodoResponse, ListTodosRequest, ListTodosResponse, Todo, TodosService, TodosServiceClient,
+};
+
+struct TestServer;
+
+#[connect_rs_impl(generated::todos::v1::TodosService)]
+impl TestServer {
+    /// Uses `x-task` as the todo's task, and sends its id back as a header and a trailer
+    async fn get_todo(
+        &self,
+        req: connect_axum::Request<GetTodoRequest>,
+    ) -> Result<Response<GetTodoResponse>, ConnectError> {
+        let task = req.headers().get("x-task").cloned();
+        let id = req.into_message().id;
+
+        match id.as_str() {
+            "missing" => return Err(not_found(id)),
+            // Outlives the timeout of any test
+            "slow" => tokio::time::sleep(Duration::from_secs(60)).await,
+            _ => {}
+        }
+
+        let id_value = HeaderValue::try_from(&id).unwrap();
+        let mut todo = todo(id);
+        if let Some(task) = task {
+            todo.task = task.to_str().unwrap().to_string();
+        }
+
+        let mut response = Response::new(GetTodoResponse { todo: Some(todo) });
+        response.headers_mut().insert("x-todo-id", id_value.clone());
+        response.trailers_mut().insert("x-todo-id", id_value);
+
+        Ok(response)
+    }
+
+    async fn list_todos(
+        &self,
+        _req: ListTodosRequest,
+    ) -> Result<Response<ResponseStream<ListTodosResponse>>, ConnectError> {
+        let mut response = Response::new(ResponseStream::new(stream::iter(["a", "b"].map(|id| {
+            Ok(ListTodosResponse {
+                todo: Some(todo(id.to_string())),
+            })
+        }))));
+        response
+            .trailers_mut()
+            .insert("x-todos-listed", HeaderValue::from_static("2"));
+
+        Ok(response)
+    }
+
+    async fn add_todos(
+        &self,
+        requests: RequestStream<AddTodosRequest>,
+    ) -> Result<AddTodosResponse, ConnectError> {
+        Ok(AddTodosResponse {
+            added: requests.count().await as u32,
+        })
+    }
+
+    async fn complete_todos(
+        &self,
+        requests: RequestStream<CompleteTodosRequest>,
+    ) -> Result<ResponseStream<CompleteTodosResponse>, ConnectError> {
+        Ok(ResponseStream::new(requests.map(|request| {
+            let id = request?.id;
+            if id == "missing" {
+                return Err(not_found(id));
+            }
+
+            Ok(CompleteTodosResponse {
+                todo: Some(todo(id)),
+            })
+        })))
+    }
+
+    async fn clear_completed(&self, _req: Empty) -> Result<Empty, ConnectError> {
+        Ok(Empty {})
+    }
+}
+
+fn todo(id: String) -> Todo {
+    Todo {
+        id,
+        task: "test".to_string(),
+        done: false,
+    }
+}
+
+/// An error with a detail and metadata, for the client to decode
+fn not_found(id: String) -> ConnectError {
+    ConnectError::new(Code::NotFound, "No such TODO")
+        .with_detail(&ResourceInfo {
+            resource_type: "todos.v1.Todo".to_string(),
+            resource_name: id,
+            ..Default::default()
+        })
+        .with_metadata(RETRY_AFTER, HeaderValue::from_static("30"))
+}
+
+/// The head of a request a client sent
+struct Sent {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+}
+
+impl Sent {
+    /// The pairs of the request's query
+    fn query(&self) -> HashMap<&str, &str> {
+        self.uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .collect()
+    }
+}
+
+/// A transport recording the head of each request on its way to the router
+#[derive(Clone)]
+struct Recording {
+    router: ServiceTransport<Router>,
+    sent: Arc<Mutex<Vec<Sent>>>,
+}
+
+impl Transport for Recording {
+    async fn send(
+        &self,
+        request: http::Request<Body>,
+    ) -> Result<http::Response<Body>, ConnectError> {
+        self.sent.lock().unwrap().push(Sent {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+            headers: request.headers().clone(),
+        });
+
+        self.router.send(request).await
+    }
+}
+
+/// A client recording the requests it sends to the router
+fn recording_client() -> (TodosServiceClient<Recording>, Arc<Mutex<Vec<Sent>>>) {
+    let sent = Arc::default();
+    let transport = Recording {
+        router: ServiceTransport::new(TestServer.into_router()),
+        sent: Arc::clone(&sent),
+    };
+
+    (
+        TodosServiceClient::with_transport("http://localhost", transport),
+        sent,
+    )
+}
+
+/// A client calling `router` in-process
+fn client_of(router: Router) -> TodosServiceClient<ServiceTransport<Router>> {
+    TodosServiceClient::with_transport("http://localhost", ServiceTransport::new(router))
+}
+
+fn client() -> TodosServiceClient<ServiceTransport<Router>> {
+    client_of(TestServer.into_router())
+}
+
+#[tokio::test]
+async fn unary_errors_are_decoded() {
+    let error = client()
+        .get_todo(GetTodoRequest {
+            id: "missing".into(),
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.code(), Code::NotFound);
+    assert_eq!(error.message(), "No such TODO");
+    assert_eq!(error.metadata()[RETRY_AFTER], "30");
+
+    let resource = error.detail::<ResourceInfo>().unwrap();
+    assert_eq!(resource.resource_name, "missing");
+}
+
+#[tokio::test]
+async fn stream_errors_are_decoded() {
+    let requests =
+        stream::iter(["a", "missing", "b"].map(|id| CompleteTodosRequest { id: id.into() }));
+    let mut responses = client().complete_todos(requests).await.unwrap();
+
+    let first = responses.next().await.unwrap().unwrap();
+    assert_eq!(first.todo.unwrap().id, "a");
+
+    let error = responses.next().await.unwrap().unwrap_err();
+    assert_eq!(error.code(), Code::NotFound);
+    assert_eq!(error.metadata()[RETRY_AFTER], "30");
+    assert_eq!(
+        error.detail::<ResourceInfo>().unwrap().resource_name,
+        "missing"
+    );
+
+    assert!(responses.next().await.is_none());
+}
+
+#[tokio::test]
+async fn other_http_errors_get_a_code_from_their_status() {
+    for (status, code) in [
+        (StatusCode::BAD_REQUEST, Code::Internal),
+        (StatusCode::UNAUTHORIZED, Code::Unauthenticated),
+        (StatusCode::FORBIDDEN, Code::PermissionDenied),
+        (StatusCode::NOT_FOUND, Code::Unimplemented),
+        (StatusCode::TOO_MANY_REQUESTS, Code::Unavailable),
+        (StatusCode::BAD_GATEWAY, Code::Unavailable),
+        (StatusCode::SERVICE_UNAVAILABLE, Code::Unavailable),
+        (StatusCode::GATEWAY_TIMEOUT, Code::Unavailable),
+        (StatusCode::INTERNAL_SERVER_ERROR, Code::Unknown),
+    ] {
+        // Like a proxy in front of the server, answering in plain text
+        let proxy = Router::new().fallback(move || async move { (status, "Not a Connect error") });
+
+        let error = client_of(proxy.clone())
+            .get_todo(GetTodoRequest { id: "a".into() })
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), code, "for {status}");
+
+        let error = client_of(proxy)
+            .list_todos(ListTodosRequest {})
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.code(), code, "for {status}");
+    }
+}
+
+#[tokio::test]
+async fn proto_is_the_default_encoding() {
+    let (client, sent) = recording_client();
+
+    let response = client
+        .get_todo(GetTodoRequest { id: "a".into() })
+        .await
+        .unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+
+    assert_eq!(
+        sent.lock().unwrap()[0].headers[CONTENT_TYPE],
+        "application/proto"
+    );
+}
+
+#[tokio::test]
+async fn json_clients_send_and_receive_json() {
+    let (client, sent) = recording_client();
+    let client = client.with_encoding(Encoding::json());
+
+    let response = client
+        .get_todo(GetTodoRequest { id: "a".into() })
+        .await
+        .unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+
+    let ids: Vec<_> = client
+        .list_todos(ListTodosRequest {})
+        .await
+        .unwrap()
+        .map(|response| response.unwrap().todo.unwrap().id)
+        .collect()
+        .await;
+    assert_eq!(ids, ["a", "b"]);
+
+    let requests = stream::iter(["a", "b", "c"].map(|id| AddTodosRequest {
+        todo: Some(todo(id.into())),
+    }));
+    let response = client.add_todos(requests).await.unwrap();
+    assert_eq!(response.added, 3);
+
+    let content_types: Vec<_> = sent
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|sent| sent.headers[CONTENT_TYPE].clone())
+        .collect();
+    assert_eq!(
+        content_types,
+        [
+            "application/json",
+            "application/connect+json",
+            "application/connect+json"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn get_requests_carry_the_message_in_the_query() {
+    let (client, sent) = recording_client();
+    let client = client.with_get_requests(true);
+
+    let response = client
+        .get_todo(GetTodoRequest { id: "a".into() })
+        .await
+        .unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+
+    let sent = &sent.lock().unwrap()[0];
+    assert_eq!(sent.method, Method::GET);
+    assert_eq!(sent.uri.path(), "/todos.v1.TodosService/GetTodo");
+    assert!(!sent.headers.contains_key(CONTENT_TYPE));
+
+    let query = sent.query();
+    assert_eq!(query["connect"], "v1");
+    assert_eq!(query["encoding"], "proto");
+    assert_eq!(query["base64"], "1");
+
+    let message = URL_SAFE_NO_PAD.decode(query["message"]).unwrap();
+    assert_eq!(
+        GetTodoRequest::decode(message.as_slice()).unwrap(),
+        GetTodoRequest { id: "a".into() }
+    );
+}
+
+#[tokio::test]
+async fn get_requests_in_json() {
+    let (client, sent) = recording_client();
+    let client = client
+        .with_encoding(Encoding::json())
+        .with_get_requests(true);
+
+    let response = client
+        .get_todo(GetTodoRequest { id: "a".into() })
+        .await
+        .unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+
+    let sent = &sent.lock().unwrap()[0];
+    assert_eq!(sent.method, Method::GET);
+
+    let query = sent.query();
+    assert_eq!(query["encoding"], "json");
+    assert_eq!(
+        URL_SAFE_NO_PAD.decode(query["message"]).unwrap(),
+        br#"{"id":"a"}"#
+    );
+}
+
+#[tokio::test]
+async fn methods_with_side_effects_are_still_posted() {
+    let (client, sent) = recording_client();
+    let client = client.with_get_requests(true);
+
+    client.clear_completed(Empty {}).await.unwrap();
+
+    let sent = &sent.lock().unwrap()[0];
+    assert_eq!(sent.method, Method::POST);
+    assert_eq!(sent.uri.query(), None);
+}
+
+#[tokio::test]
+async fn call_options_headers_are_sent() {
+    let response = client()
+        .get_todo_with(
+            GetTodoRequest { id: "a".into() },
+            CallOptions::new().header("x-task", HeaderValue::from_static("From a header")),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.message().todo.as_ref().unwrap().task,
+        "From a header"
+    );
+}
+
+#[tokio::test]
+async fn responses_carry_headers_and_trailers() {
+    let response = client()
+        .get_todo_with(GetTodoRequest { id: "a".into() }, CallOptions::new())
+        .await
+        .unwrap();
+
+    assert_eq!(response.headers()["x-todo-id"], "a");
+    assert_eq!(response.trailers()["x-todo-id"], "a");
+    assert!(!response.headers().contains_key("trailer-x-todo-id"));
+
+    let mut responses = client()
+        .list_todos_with(ListTodosRequest {}, CallOptions::new())
+        .await
+        .unwrap()
+        .into_message();
+    while responses.next().await.is_some() {}
+
+    assert_eq!(responses.trailers().unwrap()["x-todos-listed"], "2");
+}
+
+#[tokio::test]
+async fn call_options_timeout_is_sent_and_enforced() {
+    let (client, sent) = recording_client();
+
+    let error = client
+        .get_todo_with(
+            GetTodoRequest { id: "slow".into() },
+            CallOptions::new().timeout(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Code::DeadlineExceeded);
+
+    assert_eq!(sent.lock().unwrap()[0].headers["connect-timeout-ms"], "50");
+}
+
+#[tokio::test]
+async fn hyper_transport_calls_a_server() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(axum::serve(listener, TestServer.into_router()).into_future());
+
+    let client =
+        TodosServiceClient::with_transport(format!("http://{address}"), HyperTransport::default());
+
+    let response = client
+        .get_todo(GetTodoRequest { id: "a".into() })
+        .await
+        .unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+
+    let requests = stream::iter(["a", "b"].map(|id| CompleteTodosRequest { id: id.into() }));
+    let ids: Vec<_> = client
+        .complete_todos(requests)
+        .await
+        .unwrap()
+        .map(|response| response.unwrap().todo.unwrap().id)
+        .collect()
+        .await;
+    assert_eq!(ids, ["a", "b"]);
+
+    let error = client
+        .get_todo(GetTodoRequest {
+            id: "missing".into(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Code::NotFound);
+}
@@ -0,0 +1,153 @@
+//! Code generated with connect-build's default options, so without serde impls for the messages
+//! (which can't have any, with their fields of well-known types): sent as Protobuf, or as JSON
+//! through canonical JSON
+mod clock {
+    connect_axum::include_proto!("clock.v1");
+}
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode, header::CONTENT_TYPE};
+use http_body_util::BodyExt;
+use prost::Message;
+use prost_types::Timestamp;
+use tower::ServiceExt;
+
+use clock::{Alarm, ClockService, SetAlarmRequest, SetAlarmResponse};
+use connect_axum::{Empty, RouterOptions, connect_rs_impl};
+
+const NOW: Timestamp = Timestamp {
+    seconds: 1_700_000_000,
+    nanos: 0,
+};
+
+struct TestClock;
+
+#[connect_rs_impl(clock::ClockService)]
+impl TestClock {
+    async fn now(&self, _req: Empty) -> Result<Timestamp, connect_axum::ConnectError> {
+        Ok(NOW)
+    }
+
+    async fn set_alarm(
+        &self,
+        req: SetAlarmRequest,
+    ) -> Result<SetAlarmResponse, connect_axum::ConnectError> {
+        Ok(SetAlarmResponse { alarm: req.alarm })
+    }
+}
+
+/// A unary `POST` to the method at `path`
+fn post(path: &str, content_type: &str, body: impl Into<Body>) -> Request<Body> {
+    Request::post(format!("/clock.v1.ClockService/{path}"))
+        .header(CONTENT_TYPE, content_type)
+        .body(body.into())
+        .unwrap()
+}
+
+/// The JSON response of the method at `path` to `request`, with canonical JSON
+async fn canonical_json(path: &str, request: &'static str) -> serde_json::Value {
+    let router = TestClock
+        .into_router_with(RouterOptions::new().canonical_json(true))
+        .unwrap();
+    let response = router
+        .oneshot(post(path, "application/json", request))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn well_known_types_are_rpc_types() {
+    let response = TestClock
+        .into_router()
+        .oneshot(post("Now", "application/proto", Body::empty()))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(Timestamp::decode(body).unwrap(), NOW);
+}
+
+#[tokio::test]
+async fn messages_without_serde_impls_are_sent_as_protobuf() {
+    let alarm = Alarm {
+        id: 1,
+        ring_at: Some(NOW),
+        repeat: clock::Repeat::Daily.into(),
+    };
+    let request = SetAlarmRequest { alarm: Some(alarm) };
+
+    let response = TestClock
+        .into_router()
+        .oneshot(post(
+            "SetAlarm",
+            "application/proto",
+            request.encode_to_vec(),
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(SetAlarmResponse::decode(body).unwrap().alarm, Some(alarm));
+}
+
+#[tokio::test]
+async fn messages_without_serde_impls_fail_as_json() {
+    let response = TestClock
+        .into_router()
+        .oneshot(post("SetAlarm", "application/json", "{}"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn canonical_json_encodes_well_known_types() {
+    let response = canonical_json("Now", "{}").await;
+
+    assert_eq!(response, "2023-11-14T22:13:20Z");
+}
+
+#[tokio::test]
+async fn canonical_json_round_trips_messages() {
+    let request = r#"{"alarm":{"id":"1","ringAt":"2023-11-14T22:13:20Z","repeat":"REPEAT_DAILY"}}"#;
+    let response = canonical_json("SetAlarm", request).await;
+
+    // 64-bit integers as strings, timestamps in RFC 3339 and enums by name
+    assert_eq!(
+        response,
+        serde_json::json!({
+            "alarm": {
+                "id": "1",
+                "ringAt": "2023-11-14T22:13:20Z",
+                "repeat": "REPEAT_DAILY",
+            }
+        })
+    );
+}
+
+#[tokio::test]
+async fn canonical_json_accepts_original_names_and_unknown_fields() {
+    let request = r#"{"alarm":{"id":2,"ring_at":"2023-11-14T22:13:20Z","snooze":true}}"#;
+    let response = canonical_json("SetAlarm", request).await;
+
+    // Default values (like the unset `repeat`) are left out
+    assert_eq!(
+        response,
+        serde_json::json!({
+            "alarm": {
+                "id": "2",
+                "ringAt": "2023-11-14T22:13:20Z",
+            }
+        })
+    );
+}
@@ -0,0 +1,1023 @@
+//! Requests sent straight to the generated router, without a server or client in between
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/mod.rs"));
+}
+
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{self, HeaderMap, HeaderValue, Request, StatusCode};
+use http_body_util::BodyExt;
+use prost::Message;
+use tower::ServiceExt;
+
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use connect_axum::codec::{DecodeMessage, EncodeMessage};
+use connect_axum::deadline;
+use connect_axum::envelope::{Envelope, EnvelopeStream, FLAG_COMPRESSED};
+use connect_axum::grpc;
+use connect_axum::{
+    Code, Codec, Compression, Empty, MessageLimits, RequestStream, Response, ResponseStream,
+    RouterOptions, connect_rs_impl,
+};
+use futures_util::{StreamExt, stream};
+
+use generated::todos::v1::{
+    AddTodosRequest, AddTodosResponse, CompleteTodosRequest, CompleteTodosResponse, GetTodoRequest,
+    GetTodoResponse, ListTodosRequest, ListTodosResponse, Todo, TodosService,
+};
+
+struct TestServer;
+
+#[connect_rs_impl(generated::todos::v1::TodosService)]
+impl TestServer {
+    async fn get_todo(
+        &self,
+        req: GetTodoRequest,
+    ) -> Result<GetTodoResponse, connect_axum::ConnectError> {
+        match req.id.as_str() {
+            // Outlives the timeout of any test
+            "slow" => tokio::time::sleep(Duration::from_secs(60)).await,
+            // Reports the milliseconds left before the deadline as the task
+            "remaining" => {
+                let remaining = deadline::remaining().map_or(0, |remaining| remaining.as_millis());
+                return Ok(GetTodoResponse {
+                    todo: Some(Todo {
+                        task: remaining.to_string(),
+                        ..todo(req.id)
+                    }),
+                });
+            }
+            "unavailable" => return Err(unavailable()),
+            // Attaches the todo to the error as a detail
+            "invalid" => {
+                return Err(connect_axum::ConnectError::invalid_argument("Invalid TODO")
+                    .with_detail(&todo(req.id)));
+            }
+            _ => {}
+        }
+
+        Ok(GetTodoResponse {
+            todo: Some(todo(req.id)),
+        })
+    }
+
+    async fn list_todos(
+        &self,
+        _req: ListTodosRequest,
+    ) -> Result<Response<ResponseStream<ListTodosResponse>>, connect_axum::ConnectError> {
+        let mut response = Response::new(ResponseStream::new(stream::iter(["a", "b"].map(|id| {
+            Ok(ListTodosResponse {
+                todo: Some(todo(id.to_string())),
+            })
+        }))));
+        response
+            .trailers_mut()
+            .insert("x-todos-listed", HeaderValue::from_static("2"));
+
+        Ok(response)
+    }
+
+    async fn add_todos(
+        &self,
+        requests: RequestStream<AddTodosRequest>,
+    ) -> Result<AddTodosResponse, connect_axum::ConnectError> {
+        Ok(AddTodosResponse {
+            added: requests.count().await as u32,
+        })
+    }
+
+    async fn complete_todos(
+        &self,
+        requests: RequestStream<CompleteTodosRequest>,
+    ) -> Result<ResponseStream<CompleteTodosResponse>, connect_axum::ConnectError> {
+        Ok(ResponseStream::new(requests.map(|request| {
+            let id = request?.id;
+            if id == "unavailable" {
+                return Err(unavailable());
+            }
+
+            Ok(CompleteTodosResponse {
+                todo: Some(todo(id)),
+            })
+        })))
+    }
+
+    /// Echoes `x-echo` back as a header, and counts what it cleared in a trailer
+    async fn clear_completed(
+        &self,
+        req: connect_axum::Request<Empty>,
+    ) -> Result<Response<Empty>, connect_axum::ConnectError> {
+        let mut response = Response::new(Empty {});
+
+        if let Some(echo) = req.headers().get("x-echo") {
+            response.headers_mut().insert("x-echo", echo.clone());
+        }
+        response
+            .trailers_mut()
+            .insert("x-todos-cleared", HeaderValue::from_static("0"));
+
+        Ok(response)
+    }
+}
+
+/// An error with metadata, telling the client when to retry
+fn unavailable() -> connect_axum::ConnectError {
+    connect_axum::ConnectError::new(Code::Unavailable, "Try again later")
+        .with_metadata(RETRY_AFTER, HeaderValue::from_static("30"))
+}
+
+/// Protobuf written out as hex digits, as `hex`
+struct HexCodec;
+
+impl Codec for HexCodec {
+    fn name(&self) -> &str {
+        "hex"
+    }
+
+    fn uses_serde(&self) -> bool {
+        false
+    }
+
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, connect_axum::ConnectError> {
+        let bytes = message.encode_proto()?;
+        Ok(bytes
+            .iter()
+            .flat_map(|byte| format!("{byte:02x}").into_bytes())
+            .collect())
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        message: &mut dyn DecodeMessage,
+    ) -> Result<(), connect_axum::ConnectError> {
+        let bytes = bytes
+            .chunks(2)
+            .map(|digits| {
+                std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| connect_axum::ConnectError::invalid_argument("Invalid hex"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        message.decode_proto(&bytes)
+    }
+}
+
+/// `google.rpc.Status`, as sent in `grpc-status-details-bin`
+#[derive(Clone, PartialEq, Message)]
+struct Status {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<prost_types::Any>,
+}
+
+fn todo(id: String) -> Todo {
+    Todo {
+        id,
+        task: "test".to_string(),
+        done: false,
+    }
+}
+
+/// A `POST` to the method at `path` with `content_type`
+fn post(path: &str, content_type: &str) -> http::request::Builder {
+    Request::post(format!("/todos.v1.TodosService/{path}")).header(CONTENT_TYPE, content_type)
+}
+
+struct Reply {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    trailers: Option<HeaderMap>,
+}
+
+impl Reply {
+    fn content_type(&self) -> &str {
+        self.headers
+            .get(CONTENT_TYPE)
+            .map_or("", |value| value.to_str().unwrap())
+    }
+}
+
+/// Sends `request` straight to the router, reading the whole response
+async fn send(request: Request<Body>) -> Reply {
+    send_with(RouterOptions::default(), request).await
+}
+
+/// Sends `request` straight to a router built with `options`
+async fn send_with(options: RouterOptions, request: Request<Body>) -> Reply {
+    let router = TestServer.into_router_with(options).unwrap();
+    let response = router.oneshot(request).await.unwrap();
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let collected = response.into_body().collect().await.unwrap();
+    let trailers = collected.trailers().cloned();
+
+    Reply {
+        status,
+        headers,
+        body: collected.to_bytes(),
+        trailers,
+    }
+}
+
+/// Frames each of `messages` in an envelope
+fn envelopes(messages: &[&[u8]]) -> Vec<u8> {
+    messages
+        .iter()
+        .flat_map(|message| Envelope::message(message.to_vec()).encode().unwrap())
+        .collect()
+}
+
+/// Splits `body` back into envelopes
+async fn read_envelopes(body: Bytes) -> Vec<Envelope> {
+    EnvelopeStream::new(Body::from(body))
+        .map(Result::unwrap)
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn empty_is_an_empty_json_object() {
+    let reply = send(
+        post("ClearCompleted", "application/json")
+            .body("{}".into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/json");
+    assert_eq!(reply.body, "{}");
+}
+
+#[tokio::test]
+async fn empty_is_an_empty_proto_message() {
+    let reply = send(
+        post("ClearCompleted", "application/proto")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/proto");
+    assert!(reply.body.is_empty());
+}
+
+#[tokio::test]
+async fn connect_unary() {
+    let reply = send(
+        post("GetTodo", "application/json")
+            .body(r#"{"id":"a"}"#.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/json");
+
+    let response: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(response["todo"]["id"], "a");
+}
+
+#[tokio::test]
+async fn connect_unary_proto() {
+    let request = GetTodoRequest { id: "a".into() }.encode_to_vec();
+    let reply = send(
+        post("GetTodo", "application/proto")
+            .body(request.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    let response = GetTodoResponse::decode(reply.body).unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+}
+
+#[tokio::test]
+async fn connect_server_stream() {
+    let reply = send(
+        post("ListTodos", "application/connect+json")
+            .body(envelopes(&[b"{}"]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/connect+json");
+
+    let mut envelopes = read_envelopes(reply.body).await;
+    let end_stream = envelopes.pop().unwrap().into_end_stream().unwrap();
+    assert!(end_stream.error.is_none());
+
+    let ids: Vec<_> = envelopes
+        .iter()
+        .map(|envelope| {
+            let response: serde_json::Value = serde_json::from_slice(&envelope.payload).unwrap();
+            response["todo"]["id"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(ids, ["a", "b"]);
+}
+
+#[tokio::test]
+async fn connect_client_stream() {
+    let request = AddTodosRequest {
+        todo: Some(todo("x".into())),
+    }
+    .encode_to_vec();
+    let requests = [request.as_slice(); 3];
+
+    let reply = send(
+        post("AddTodos", "application/connect+proto")
+            .body(envelopes(&requests).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+
+    let envelopes = read_envelopes(reply.body).await;
+    assert_eq!(envelopes.len(), 2);
+    assert_eq!(
+        AddTodosResponse::decode(envelopes[0].payload.clone()).unwrap(),
+        AddTodosResponse { added: 3 }
+    );
+    assert!(envelopes[1].is_end_stream());
+}
+
+#[tokio::test]
+async fn connect_bidi_stream() {
+    let requests = ["a", "b"].map(|id| CompleteTodosRequest { id: id.into() }.encode_to_vec());
+    let requests = requests.each_ref().map(Vec::as_slice);
+
+    let reply = send(
+        post("CompleteTodos", "application/connect+proto")
+            .body(envelopes(&requests).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/connect+proto");
+
+    let mut envelopes = read_envelopes(reply.body).await;
+    let end_stream = envelopes.pop().unwrap().into_end_stream().unwrap();
+    assert!(end_stream.error.is_none());
+
+    let ids: Vec<_> = envelopes
+        .into_iter()
+        .map(|envelope| {
+            let response = CompleteTodosResponse::decode(envelope.payload).unwrap();
+            response.todo.unwrap().id
+        })
+        .collect();
+    assert_eq!(ids, ["a", "b"]);
+}
+
+#[tokio::test]
+async fn connect_unary_unsupported_content_type() {
+    let reply = send(
+        post("GetTodo", "text/plain")
+            .body(r#"{"id":"a"}"#.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let accept_post = reply.headers["accept-post"].to_str().unwrap();
+    assert!(accept_post.contains("application/json"), "{accept_post}");
+    assert!(accept_post.contains("application/proto"), "{accept_post}");
+}
+
+#[tokio::test]
+async fn connect_get_of_a_method_with_side_effects() {
+    let reply = send(
+        Request::get(
+            "/todos.v1.TodosService/ClearCompleted?connect=v1&encoding=json&message=%7B%7D",
+        )
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(reply.headers[http::header::ALLOW], "POST");
+}
+
+#[tokio::test]
+async fn connect_stream_errors_before_the_stream_end_it() {
+    let reply = send(
+        post("ListTodos", "application/connect+json")
+            .header("connect-content-encoding", "snappy")
+            .body(envelopes(&[b"{}"]).into())
+            .unwrap(),
+    )
+    .await;
+
+    // Reported like any error of the stream, not as an HTTP error
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/connect+json");
+
+    let mut envelopes = read_envelopes(reply.body).await;
+    assert_eq!(envelopes.len(), 1);
+
+    let error = envelopes
+        .remove(0)
+        .into_end_stream()
+        .unwrap()
+        .error
+        .unwrap();
+    assert_eq!(error.code(), Code::Unimplemented);
+}
+
+#[tokio::test]
+async fn connect_stream_unsupported_content_type() {
+    let reply = send(post("ListTodos", "text/plain").body("{}".into()).unwrap()).await;
+
+    assert_eq!(reply.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn connect_timeout_cancels_the_handler() {
+    let reply = send(
+        post("GetTodo", "application/json")
+            .header("connect-timeout-ms", "50")
+            .body(r#"{"id":"slow"}"#.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::GATEWAY_TIMEOUT);
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "deadline_exceeded");
+}
+
+#[tokio::test]
+async fn grpc_timeout_cancels_the_handler() {
+    let request = GetTodoRequest { id: "slow".into() }.encode_to_vec();
+    let reply = send(
+        post("GetTodo", "application/grpc")
+            .header("grpc-timeout", "50m")
+            .body(envelopes(&[&request]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.trailers.unwrap()["grpc-status"], "4");
+}
+
+#[tokio::test]
+async fn handlers_see_the_time_remaining() {
+    let reply = send(
+        post("GetTodo", "application/json")
+            .header("connect-timeout-ms", "10000")
+            .body(r#"{"id":"remaining"}"#.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    let response: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    let remaining: u64 = response["todo"]["task"].as_str().unwrap().parse().unwrap();
+    assert!(
+        remaining > 0 && remaining <= 10_000,
+        "{remaining}ms remaining"
+    );
+}
+
+#[tokio::test]
+async fn connect_timeout_allows_at_most_ten_digits() {
+    let get_todo = |timeout_ms: &str| {
+        post("GetTodo", "application/json")
+            .header("connect-timeout-ms", timeout_ms)
+            .body(r#"{"id":"a"}"#.into())
+            .unwrap()
+    };
+
+    let reply = send(get_todo("9999999999")).await;
+    assert_eq!(reply.status, StatusCode::OK);
+
+    let reply = send(get_todo("10000000000")).await;
+    assert_eq!(reply.status, StatusCode::BAD_REQUEST);
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "invalid_argument");
+}
+
+#[tokio::test]
+async fn connect_error_details_are_in_the_json_body() {
+    let reply = send(
+        post("GetTodo", "application/json")
+            .body(r#"{"id":"invalid"}"#.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::BAD_REQUEST);
+
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "invalid_argument");
+    assert_eq!(error["details"][0]["type"], "todos.v1.Todo");
+
+    let value = error["details"][0]["value"].as_str().unwrap();
+    let value = STANDARD_NO_PAD.decode(value).unwrap();
+    assert_eq!(
+        Todo::decode(value.as_slice()).unwrap(),
+        todo("invalid".into())
+    );
+}
+
+#[tokio::test]
+async fn grpc_error_details_are_in_grpc_status_details_bin() {
+    let request = GetTodoRequest {
+        id: "invalid".into(),
+    }
+    .encode_to_vec();
+    let reply = send(
+        post("GetTodo", "application/grpc")
+            .body(envelopes(&[&request]).into())
+            .unwrap(),
+    )
+    .await;
+
+    let trailers = reply.trailers.unwrap();
+    assert_eq!(trailers["grpc-status"], "3");
+
+    let status = STANDARD_NO_PAD
+        .decode(&trailers["grpc-status-details-bin"])
+        .unwrap();
+    let status = Status::decode(status.as_slice()).unwrap();
+    assert_eq!(status.code, 3);
+    assert_eq!(status.message, "Invalid TODO");
+    assert_eq!(
+        status.details[0].type_url,
+        "type.googleapis.com/todos.v1.Todo"
+    );
+    assert_eq!(
+        Todo::decode(status.details[0].value.as_slice()).unwrap(),
+        todo("invalid".into())
+    );
+}
+
+#[tokio::test]
+async fn connect_unary_error_metadata_is_sent_as_headers() {
+    let reply = send(
+        post("GetTodo", "application/json")
+            .body(r#"{"id":"unavailable"}"#.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(reply.headers[RETRY_AFTER], "30");
+
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "unavailable");
+}
+
+#[tokio::test]
+async fn connect_stream_error_metadata_is_in_the_end_stream_message() {
+    let requests =
+        ["a", "unavailable"].map(|id| CompleteTodosRequest { id: id.into() }.encode_to_vec());
+    let requests = requests.each_ref().map(Vec::as_slice);
+
+    let reply = send(
+        post("CompleteTodos", "application/connect+proto")
+            .body(envelopes(&requests).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert!(!reply.headers.contains_key(RETRY_AFTER));
+
+    let mut envelopes = read_envelopes(reply.body).await;
+    assert_eq!(envelopes.len(), 2);
+
+    let end_stream = envelopes.pop().unwrap().into_end_stream().unwrap();
+    assert_eq!(end_stream.error.unwrap().code(), Code::Unavailable);
+    assert_eq!(end_stream.metadata[RETRY_AFTER], "30");
+}
+
+#[tokio::test]
+async fn connect_unary_headers_and_trailers() {
+    let reply = send(
+        post("ClearCompleted", "application/json")
+            .header("x-echo", "hello")
+            .body("{}".into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.headers["x-echo"], "hello");
+    assert_eq!(reply.headers["trailer-x-todos-cleared"], "0");
+    assert!(!reply.headers.contains_key("x-todos-cleared"));
+}
+
+#[tokio::test]
+async fn connect_stream_trailers_are_in_the_end_stream_message() {
+    let reply = send(
+        post("ListTodos", "application/connect+json")
+            .body(envelopes(&[b"{}"]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert!(!reply.headers.contains_key("trailer-x-todos-listed"));
+
+    let end_stream = read_envelopes(reply.body)
+        .await
+        .pop()
+        .unwrap()
+        .into_end_stream()
+        .unwrap();
+    assert!(end_stream.error.is_none());
+    assert_eq!(end_stream.metadata["x-todos-listed"], "2");
+}
+
+#[tokio::test]
+async fn grpc_trailers_are_http_trailers() {
+    let reply = send(
+        post("ClearCompleted", "application/grpc")
+            .header("x-echo", "hello")
+            .body(envelopes(&[b""]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.headers["x-echo"], "hello");
+
+    let trailers = reply.trailers.unwrap();
+    assert_eq!(trailers["grpc-status"], "0");
+    assert_eq!(trailers["x-todos-cleared"], "0");
+}
+
+#[tokio::test]
+async fn strict_mode_requires_the_protocol_version() {
+    let strict = || RouterOptions::new().strict(true);
+    let get_todo = || post("GetTodo", "application/json");
+
+    let reply = send_with(strict(), get_todo().body(r#"{"id":"a"}"#.into()).unwrap()).await;
+    assert_eq!(reply.status, StatusCode::BAD_REQUEST);
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "invalid_argument");
+
+    let reply = send_with(
+        strict(),
+        get_todo()
+            .header("connect-protocol-version", "1")
+            .body(r#"{"id":"a"}"#.into())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::OK);
+
+    // Without strict mode, the header is optional
+    let reply = send(get_todo().body(r#"{"id":"a"}"#.into()).unwrap()).await;
+    assert_eq!(reply.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn strict_mode_requires_connect_v1_in_gets() {
+    let get = |query: &str| {
+        Request::get(format!("/todos.v1.TodosService/GetTodo?{query}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let message = "encoding=json&message=%7B%22id%22%3A%22a%22%7D";
+
+    let reply = send_with(RouterOptions::new().strict(true), get(message)).await;
+    assert_eq!(reply.status, StatusCode::BAD_REQUEST);
+
+    let reply = send_with(
+        RouterOptions::new().strict(true),
+        get(&format!("connect=v1&{message}")),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn strict_mode_reports_streams_without_the_protocol_version_in_the_end_stream() {
+    let reply = send_with(
+        RouterOptions::new().strict(true),
+        post("ListTodos", "application/connect+json")
+            .body(envelopes(&[b"{}"]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+
+    let end_stream = read_envelopes(reply.body)
+        .await
+        .pop()
+        .unwrap()
+        .into_end_stream()
+        .unwrap();
+    assert_eq!(end_stream.error.unwrap().code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn custom_codecs_are_used_for_their_content_types() {
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|byte| format!("{byte:02x}")).collect() };
+    let request = GetTodoRequest { id: "a".into() }.encode_to_vec();
+
+    let reply = send_with(
+        RouterOptions::new().codec(HexCodec),
+        post("GetTodo", "application/hex")
+            .body(hex(&request).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/hex");
+    let expected = GetTodoResponse {
+        todo: Some(todo("a".into())),
+    };
+    assert_eq!(reply.body, hex(&expected.encode_to_vec()));
+
+    // Streaming calls too
+    let reply = send_with(
+        RouterOptions::new().codec(HexCodec),
+        post("ListTodos", "application/connect+hex")
+            .body(envelopes(&[b""]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/connect+hex");
+    assert_eq!(read_envelopes(reply.body).await.len(), 3);
+
+    // Only for routers it's registered with
+    let reply = send(
+        post("GetTodo", "application/hex")
+            .body(hex(&request).into())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn method_limits_reject_bigger_messages() {
+    let options = RouterOptions::new().method_limits(
+        "/todos.v1.TodosService/GetTodo",
+        MessageLimits::new().max_receive_message_size(64),
+    );
+    let id = "a".repeat(100);
+
+    let reply = send_with(
+        options.clone(),
+        post("GetTodo", "application/json")
+            .body(format!(r#"{{"id":"{id}"}}"#).into())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::TOO_MANY_REQUESTS);
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "resource_exhausted");
+
+    // Other methods keep the router's limits
+    let request = CompleteTodosRequest { id }.encode_to_vec();
+    let reply = send_with(
+        options,
+        post("CompleteTodos", "application/connect+proto")
+            .body(envelopes(&[&request]).into())
+            .unwrap(),
+    )
+    .await;
+    let end_stream = read_envelopes(reply.body)
+        .await
+        .pop()
+        .unwrap()
+        .into_end_stream()
+        .unwrap();
+    assert!(end_stream.error.is_none());
+}
+
+#[tokio::test]
+async fn limits_apply_to_decompressed_messages() {
+    let limits =
+        || RouterOptions::new().limits(MessageLimits::new().max_receive_message_size(1024));
+    // A megabyte of JSON that gzips to about a kilobyte
+    let json = format!(r#"{{"id":"{}"}}"#, "a".repeat(1024 * 1024));
+    let compressed = Compression::Gzip.compress(json.as_bytes()).unwrap();
+    assert!(compressed.len() < 1024 * 2);
+
+    let reply = send_with(
+        limits(),
+        post("GetTodo", "application/json")
+            .header("content-encoding", "gzip")
+            .body(compressed.clone().into())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::TOO_MANY_REQUESTS);
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "resource_exhausted");
+
+    // And to the messages of streams, once decompressed
+    let envelope = Envelope::new(FLAG_COMPRESSED, compressed).encode().unwrap();
+    let reply = send_with(
+        limits(),
+        post("ListTodos", "application/connect+json")
+            .header("connect-content-encoding", "gzip")
+            .body(Body::from(envelope))
+            .unwrap(),
+    )
+    .await;
+    let error = read_envelopes(reply.body)
+        .await
+        .pop()
+        .unwrap()
+        .into_end_stream()
+        .unwrap()
+        .error
+        .unwrap();
+    assert_eq!(error.code(), Code::ResourceExhausted);
+}
+
+#[tokio::test]
+async fn send_limits_fail_bigger_responses() {
+    let reply = send_with(
+        RouterOptions::new().limits(MessageLimits::new().max_send_message_size(8)),
+        post("GetTodo", "application/json")
+            .body(r#"{"id":"a"}"#.into())
+            .unwrap(),
+    )
+    .await;
+
+    let error: serde_json::Value = serde_json::from_slice(&reply.body).unwrap();
+    assert_eq!(error["code"], "resource_exhausted");
+}
+
+#[tokio::test]
+async fn grpc_unary() {
+    let request = GetTodoRequest { id: "a".into() }.encode_to_vec();
+    let reply = send(
+        post("GetTodo", "application/grpc")
+            .body(envelopes(&[&request]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/grpc+proto");
+
+    let envelopes = read_envelopes(reply.body).await;
+    assert_eq!(envelopes.len(), 1);
+    let response = GetTodoResponse::decode(envelopes[0].payload.clone()).unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+
+    assert_eq!(reply.trailers.unwrap()["grpc-status"], "0");
+}
+
+#[tokio::test]
+async fn grpc_bidi_stream() {
+    let requests = ["a", "b", "c"].map(|id| CompleteTodosRequest { id: id.into() }.encode_to_vec());
+    let requests = requests.each_ref().map(Vec::as_slice);
+
+    let reply = send(
+        post("CompleteTodos", "application/grpc+proto")
+            .body(envelopes(&requests).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+
+    let ids: Vec<_> = read_envelopes(reply.body)
+        .await
+        .into_iter()
+        .map(|envelope| {
+            let response = CompleteTodosResponse::decode(envelope.payload).unwrap();
+            response.todo.unwrap().id
+        })
+        .collect();
+    assert_eq!(ids, ["a", "b", "c"]);
+
+    assert_eq!(reply.trailers.unwrap()["grpc-status"], "0");
+}
+
+#[tokio::test]
+async fn grpc_errors_are_trailers() {
+    let reply = send(
+        post("GetTodo", "application/grpc")
+            .header("grpc-timeout", "soon")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+
+    // Trailers-only: the status is in the headers, with no body
+    assert_eq!(reply.status, StatusCode::OK);
+    assert!(reply.body.is_empty());
+    assert_eq!(reply.headers["grpc-status"], "3");
+}
+
+/// The trailers in the last frame of a gRPC-Web response, as `name: value` lines
+fn grpc_web_trailers(envelope: &Envelope) -> String {
+    assert_eq!(envelope.flags, grpc::FLAG_TRAILERS);
+    String::from_utf8(envelope.payload.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn grpc_errors_keep_the_request_codec() {
+    let reply = send(
+        post("GetTodo", "application/grpc-web+json")
+            .header("grpc-timeout", "soon")
+            .body(envelopes(&[br#"{"id":"a"}"#]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/grpc-web+json");
+    assert_eq!(reply.headers["grpc-status"], "3");
+}
+
+#[tokio::test]
+async fn grpc_web_unary() {
+    let request = GetTodoRequest { id: "a".into() }.encode_to_vec();
+    let reply = send(
+        post("GetTodo", "application/grpc-web+proto")
+            .body(envelopes(&[&request]).into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.content_type(), "application/grpc-web+proto");
+
+    let envelopes = read_envelopes(reply.body).await;
+    assert_eq!(envelopes.len(), 2);
+
+    let response = GetTodoResponse::decode(envelopes[0].payload.clone()).unwrap();
+    assert_eq!(response.todo.unwrap().id, "a");
+    assert!(grpc_web_trailers(&envelopes[1]).contains("grpc-status: 0\r\n"));
+}
+
+#[tokio::test]
+async fn grpc_web_text_server_stream() {
+    let request = STANDARD.encode(envelopes(&[&ListTodosRequest::default().encode_to_vec()]));
+    let reply = send(
+        post("ListTodos", "application/grpc-web-text")
+            .body(request.into())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(reply.status, StatusCode::OK);
+    assert!(
+        reply
+            .content_type()
+            .starts_with("application/grpc-web-text")
+    );
+
+    // Each frame is encoded on its own, so decoded on its own too
+    let body: Vec<u8> = std::str::from_utf8(&reply.body)
+        .unwrap()
+        .split_inclusive('=')
+        .filter(|chunk| *chunk != "=")
+        .flat_map(|chunk| {
+            STANDARD
+                .decode(format!(
+                    "{chunk:=<width$}",
+                    width = chunk.len().div_ceil(4) * 4
+                ))
+                .unwrap()
+        })
+        .collect();
+
+    let mut envelopes = read_envelopes(body.into()).await;
+    assert!(grpc_web_trailers(&envelopes.pop().unwrap()).contains("grpc-status: 0\r\n"));
+
+    let ids: Vec<_> = envelopes
+        .into_iter()
+        .map(|envelope| {
+            ListTodosResponse::decode(envelope.payload)
+                .unwrap()
+                .todo
+                .unwrap()
+                .id
+        })
+        .collect();
+    assert_eq!(ids, ["a", "b"]);
+}
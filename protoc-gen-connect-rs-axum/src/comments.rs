@@ -0,0 +1,73 @@
+//! The comments of services and methods in the `.proto` files, from the source locations protoc
+//! sends along with the files to generate, as doc attributes like prost-build writes for messages.
+use proc_macro2::TokenStream;
+use prost_types::FileDescriptorProto;
+use quote::quote;
+
+// Field numbers making up the paths of source locations
+// https://protobuf.dev/reference/cpp/api-docs/google.protobuf.descriptor.pb
+const SERVICE: i32 = 6;
+const METHOD: i32 = 2;
+
+/// The leading and trailing comments of a service or method
+#[derive(Default)]
+pub struct Comments {
+    lines: Vec<String>,
+}
+
+impl Comments {
+    /// The comments of the service at `service` in `file`
+    pub fn service(file: &FileDescriptorProto, service: usize) -> Self {
+        Self::at(file, &[SERVICE, service as i32])
+    }
+
+    /// The comments of the method at `method` in the service at `service` in `file`
+    pub fn method(file: &FileDescriptorProto, service: usize, method: usize) -> Self {
+        Self::at(file, &[SERVICE, service as i32, METHOD, method as i32])
+    }
+
+    fn at(file: &FileDescriptorProto, path: &[i32]) -> Self {
+        let Some(location) = file
+            .source_code_info
+            .as_ref()
+            .and_then(|info| info.location.iter().find(|location| location.path == path))
+        else {
+            return Self::default();
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+
+        for comment in [location.leading_comments(), location.trailing_comments()] {
+            if comment.trim().is_empty() {
+                continue;
+            }
+
+            // A blank line between the leading and trailing comments keeps them paragraphs
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+
+            lines.extend(
+                comment
+                    .trim_end()
+                    .lines()
+                    .map(|line| line.trim_end().to_string()),
+            );
+        }
+
+        Self { lines }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The comments as `#[doc]` attributes, each line keeping the space after its `//`
+    pub fn to_doc(&self) -> TokenStream {
+        let lines = &self.lines;
+
+        quote! {
+            #(#[doc = #lines])*
+        }
+    }
+}
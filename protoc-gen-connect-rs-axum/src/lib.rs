@@ -0,0 +1,778 @@
+//! The code generator behind `protoc-gen-connect-rs-axum`, for build scripts too (see
+//! connect-build)
+mod comments;
+mod options;
+mod types;
+
+pub use options::{ClientTransport, Options};
+
+use comments::Comments;
+use heck::ToSnakeCase;
+use prost::Message;
+use prost_types::{
+    FileDescriptorProto, FileDescriptorSet, MethodDescriptorProto,
+    compiler::{CodeGeneratorRequest, CodeGeneratorResponse, code_generator_response::File},
+};
+use quote::{format_ident, quote};
+use std::collections::BTreeMap;
+use types::TypeResolver;
+
+const PKG: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const NO_SIDE_EFFECTS: i32 = 1; // https://protobuf.dev/reference/cpp/api-docs/google.protobuf.descriptor.pb
+
+/// Generate the services and clients of the files to generate in `request`, like the plugin does
+/// for protoc
+pub fn generate(request: &CodeGeneratorRequest) -> CodeGeneratorResponse {
+    let result =
+        Options::parse(request.parameter()).and_then(|options| generate_files(request, &options));
+
+    match result {
+        Ok(file) => CodeGeneratorResponse {
+            error: None,
+            supported_features: Some(1),
+            file,
+        },
+        Err(error) => CodeGeneratorResponse {
+            error: Some(error),
+            ..Default::default()
+        },
+    }
+}
+
+fn generate_files(request: &CodeGeneratorRequest, options: &Options) -> Result<Vec<File>, String> {
+    let types = TypeResolver::new(&request.proto_file, options);
+
+    // All the files of a package go into one output, like prost's
+    let mut packages: BTreeMap<&str, Vec<&FileDescriptorProto>> = BTreeMap::new();
+
+    for file in &request.proto_file {
+        // Only generate for files we're supposed to process
+        let file_name = file.name.as_deref().unwrap_or("");
+        if !request.file_to_generate.contains(&file_name.to_string()) {
+            continue;
+        }
+
+        // Nor for packages generated elsewhere
+        let package = file.package.as_deref().unwrap_or("");
+        if types.is_extern(&format!(".{package}")) {
+            continue;
+        }
+
+        packages.entry(package).or_default().push(file);
+    }
+
+    let mut output_files = Vec::new();
+
+    for (package, files) in &packages {
+        let prost_file = format!("{}{}", file_stem(package), options.prost_file_suffix);
+
+        let content = files.iter().map(|file| {
+            let descriptors = file_descriptor_set(file, &request.proto_file);
+            generate_file_content(file, package, &descriptors, &types, options)
+        });
+
+        // prost writes nothing for packages without messages or enums of their own
+        let include_prost_file =
+            has_prost_types(package, files, &types).then(|| quote! { include!(#prost_file); });
+
+        let generated = quote! {
+            #include_prost_file
+
+            #(#content)*
+        };
+
+        output_files.push(output_file(output_file_name(package), generated)?);
+    }
+
+    if let Some(include_file) = &options.include_file {
+        output_files.push(output_file(
+            include_file.clone(),
+            generate_include_file(&packages.keys().copied().collect::<Vec<_>>()),
+        )?);
+    }
+
+    Ok(output_files)
+}
+
+/// Whether prost generates any messages or enums for `package`, skipping those generated
+/// elsewhere like it does
+fn has_prost_types(package: &str, files: &[&FileDescriptorProto], types: &TypeResolver) -> bool {
+    let is_generated = |name: &str| {
+        let proto_type = [package, name]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .fold(String::new(), |path, segment| format!("{path}.{segment}"));
+
+        !types.is_extern(&proto_type)
+    };
+
+    files.iter().any(|file| {
+        file.message_type
+            .iter()
+            .any(|message| is_generated(message.name()))
+            || file.enum_type.iter().any(|e| is_generated(e.name()))
+    })
+}
+
+/// The name prost gives the files of `package`: the package itself, or `_` without one
+fn file_stem(package: &str) -> &str {
+    if package.is_empty() { "_" } else { package }
+}
+
+/// The name of the file generated for `package`
+fn output_file_name(package: &str) -> String {
+    format!("{}.connect.rs", file_stem(package))
+}
+
+fn output_file(name: String, generated: proc_macro2::TokenStream) -> Result<File, String> {
+    let syntax_tree: syn::File = syn::parse2(generated).map_err(|e| e.to_string())?;
+    let mut formatted = prettyplease::unparse(&syntax_tree);
+
+    formatted = format!(
+        "// @generated\n// This file is @generated by {PKG} {VERSION}\n{}",
+        formatted
+    );
+
+    Ok(File {
+        name: Some(name),
+        content: Some(formatted),
+        insertion_point: None,
+        generated_code_info: None,
+    })
+}
+
+fn is_idempotent(method: &MethodDescriptorProto) -> bool {
+    method
+        .options
+        .as_ref()
+        .and_then(|opts| opts.idempotency_level)
+        .map(|level| level == NO_SIDE_EFFECTS)
+        .unwrap_or(false)
+}
+
+/// The encoded descriptors of `file` and everything it imports, without comments and source
+/// locations, for reflection at runtime (like canonical JSON)
+fn file_descriptor_set(file: &FileDescriptorProto, all_files: &[FileDescriptorProto]) -> Vec<u8> {
+    let mut names = vec![file.name().to_string()];
+    let mut i = 0;
+
+    while i < names.len() {
+        let dependencies: Vec<_> = all_files
+            .iter()
+            .filter(|f| f.name() == names[i])
+            .flat_map(|f| f.dependency.iter().cloned())
+            .collect();
+
+        for dependency in dependencies {
+            if !names.contains(&dependency) {
+                names.push(dependency);
+            }
+        }
+
+        i += 1;
+    }
+
+    // protoc lists files in topological order, which the set keeps
+    let file = all_files
+        .iter()
+        .filter(|f| names.iter().any(|name| name == f.name()))
+        .map(|f| FileDescriptorProto {
+            source_code_info: None,
+            ..f.clone()
+        })
+        .collect();
+
+    FileDescriptorSet { file }.encode_to_vec()
+}
+
+/// A module for each package (nested like `common::v1`), including the package's generated code
+fn generate_include_file(packages: &[&str]) -> proc_macro2::TokenStream {
+    #[derive(Default)]
+    struct Module<'a> {
+        file: Option<String>,
+        modules: BTreeMap<&'a str, Module<'a>>,
+    }
+
+    fn render(module: &Module) -> proc_macro2::TokenStream {
+        let include = module.file.as_ref().map(|file| quote! { include!(#file); });
+        let modules = module.modules.iter().map(|(name, module)| {
+            let ident = types::module(name);
+            let content = render(module);
+
+            quote! {
+                pub mod #ident {
+                    #content
+                }
+            }
+        });
+
+        quote! {
+            #include
+            #(#modules)*
+        }
+    }
+
+    let mut root = Module::default();
+
+    for package in packages {
+        let module = package
+            .split('.')
+            .filter(|s| !s.is_empty())
+            .fold(&mut root, |module, segment| {
+                module.modules.entry(segment).or_default()
+            });
+
+        module.file = Some(output_file_name(package));
+    }
+
+    render(&root)
+}
+
+fn generate_file_content(
+    file: &FileDescriptorProto,
+    package: &str,
+    descriptors: &[u8],
+    types: &TypeResolver,
+    options: &Options,
+) -> proc_macro2::TokenStream {
+    let services: Vec<_> = (0..file.service.len())
+        .map(|index| generate_service(file, index, package, descriptors, types, options))
+        .collect();
+
+    quote! {
+        #(#services)*
+    }
+}
+
+/// `#[deprecated]` for services and methods marked with `option deprecated = true`
+fn deprecated(deprecated: bool) -> Option<proc_macro2::TokenStream> {
+    deprecated.then(|| quote! { #[deprecated] })
+}
+
+fn generate_service(
+    file: &FileDescriptorProto,
+    index: usize,
+    package: &str,
+    descriptors: &[u8],
+    types: &TypeResolver,
+    options: &Options,
+) -> proc_macro2::TokenStream {
+    let connect_axum = options.connect_axum();
+    let service = &file.service[index];
+    let service_name = service.name.as_deref().unwrap_or("UnknownService");
+    let service_ident = format_ident!("{}", service_name);
+    let client_ident = format_ident!("{}Client", service_name);
+    let meta_mod_name = format_ident!("__{}_meta", service_name.to_snake_case());
+    let service_path = format!("{}.{}", package, service_name);
+    let descriptors = proc_macro2::Literal::byte_string(descriptors);
+
+    let service_comments = Comments::service(file, index);
+    let service_deprecated =
+        deprecated(service.options.as_ref().and_then(|o| o.deprecated) == Some(true));
+    // The client's own impls use its deprecated struct
+    let service_allow_deprecated = service_deprecated
+        .as_ref()
+        .map(|_| quote! { #[allow(deprecated)] });
+
+    let method_docs: Vec<_> = (0..service.method.len())
+        .map(|method| Comments::method(file, index, method).to_doc())
+        .collect();
+    let method_deprecated: Vec<_> = service
+        .method
+        .iter()
+        .map(|method| deprecated(method.options.as_ref().and_then(|o| o.deprecated) == Some(true)))
+        .collect();
+
+    // MAYBE: make this more programmatic
+    let service_trait_methods: Vec<_> = service
+        .method
+        .iter()
+        .enumerate()
+        .map(|(i, method)| {
+            let method_name = method.name.as_deref().unwrap_or("unknown"); // MAYBE: error if None
+            let method_ident = format_ident!("{}", method_name.to_snake_case());
+
+            let input_ident = types.resolve(method.input_type(), package);
+            let output_ident = types.resolve(method.output_type(), package);
+
+            let request_type = if method.client_streaming() {
+                quote! { #connect_axum::RequestStream<#input_ident> }
+            } else {
+                quote! { #input_ident }
+            };
+
+            let response_type = if method.server_streaming() {
+                quote! { #connect_axum::ResponseStream<#output_ident> }
+            } else {
+                quote! { #output_ident }
+            };
+
+            let docs = &method_docs[i];
+            let deprecated = &method_deprecated[i];
+
+            quote! {
+                #docs
+                #deprecated
+                async fn #method_ident(&self, request: #request_type) -> Result<#response_type, #connect_axum::ConnectError>;
+            }
+        })
+        .collect();
+
+    let client_methods: Vec<_> = service
+        .method
+        .iter()
+        .enumerate()
+        .map(|(i, method)| {
+            let method_name = method.name.as_deref().unwrap_or("unknown");
+            let method_ident = format_ident!("{}", method_name.to_snake_case());
+            let method_const = format_ident!("{}", method_name.to_uppercase());
+
+            let input_ident = types.resolve(method.input_type(), package);
+            let output_ident = types.resolve(method.output_type(), package);
+
+            // Every method comes in two variants: a plain one, and one taking `CallOptions`
+            // and returning the response's headers and trailers along with the message
+            let method_with_ident = format_ident!("{}_with", method_name.to_snake_case());
+
+            let request_type = if method.client_streaming() {
+                quote! { impl #connect_axum::stream::Stream<Item = #input_ident> + Send + 'static }
+            } else {
+                quote! { #input_ident }
+            };
+
+            let response_type = if method.server_streaming() {
+                quote! { #connect_axum::ResponseStream<#output_ident> }
+            } else {
+                quote! { #output_ident }
+            };
+
+            let url = quote! { &format!("{}{}", self.base_url, #meta_mod_name::#method_const.path) };
+
+            let call = match (method.client_streaming(), method.server_streaming()) {
+                (false, false) => {
+                    // Only methods without side effects may be called with GETs
+                    let get = if is_idempotent(method) {
+                        quote! { self.get_requests }
+                    } else {
+                        quote! { false }
+                    };
+
+                    quote! {
+                        #connect_axum::client::unary(&self.transport, #url, &encoding, #get, request, options)
+                            .await
+                    }
+                }
+                (false, true) => quote! {
+                    #connect_axum::client::server_streaming(&self.transport, #url, &encoding, request, options)
+                        .await
+                },
+                (true, false) => quote! {
+                    let response = #connect_axum::client::streaming(&self.transport, #url, &encoding, request, options)
+                        .await?;
+
+                    #connect_axum::client::single_response(response).await
+                },
+                (true, true) => quote! {
+                    #connect_axum::client::streaming(&self.transport, #url, &encoding, request, options)
+                        .await
+                },
+            };
+
+            let docs = &method_docs[i];
+            let deprecated = &method_deprecated[i];
+
+            // The plain variant calls the deprecated `_with` one
+            let allow_deprecated = deprecated.as_ref().map(|_| quote! { #[allow(deprecated)] });
+
+            quote! {
+                #docs
+                #deprecated
+                #allow_deprecated
+                pub async fn #method_ident(
+                    &self,
+                    request: #request_type
+                ) -> Result<#response_type, #connect_axum::ConnectError> {
+                    self.#method_with_ident(request, #connect_axum::client::CallOptions::default())
+                        .await
+                        .map(#connect_axum::Response::into_message)
+                }
+
+                #docs
+                #deprecated
+                pub async fn #method_with_ident(
+                    &self,
+                    request: #request_type,
+                    options: #connect_axum::client::CallOptions,
+                ) -> Result<#connect_axum::Response<#response_type>, #connect_axum::ConnectError> {
+                    let encoding = self.encoding.clone().with_serde(
+                        #connect_axum::serde_impls!(#input_ident),
+                        #connect_axum::serde_impls!(#output_ident),
+                    );
+
+                    #call
+                }
+            }
+        })
+        .collect();
+
+    let method_metas: Vec<_> = service
+        .method
+        .iter()
+        .enumerate()
+        .map(|(i, method)| {
+            let method_name = method.name.as_deref().unwrap_or("unknown"); // MAYBE: error if unknown
+            let method_const = format_ident!("{}", method_name.to_uppercase());
+            let full_path = format!("/{}/{}", service_path, method_name);
+
+            let is_idempotent = is_idempotent(method);
+
+            let client_streaming = method.client_streaming();
+            let server_streaming = method.server_streaming();
+
+            let docs = &method_docs[i];
+
+            quote! {
+                #docs
+                pub const #method_const: MethodMeta = MethodMeta {
+                    name: #method_name,
+                    path: #full_path,
+                    idempotent: #is_idempotent,
+                    client_streaming: #client_streaming,
+                    server_streaming: #server_streaming,
+                };
+            }
+        })
+        .collect();
+
+    let service_docs = if service_comments.is_empty() {
+        quote! { #[doc = concat!("Generated service trait for ", #service_name)] }
+    } else {
+        service_comments.to_doc()
+    };
+
+    let service_trait = options.server.then(|| {
+        quote! {
+            #[allow(unused)]
+            #service_docs
+            #service_deprecated
+            pub trait #service_ident: Send + Sync + 'static {
+                #(
+                    #service_trait_methods
+                )*
+            }
+        }
+    });
+
+    // The default transport, and a constructor taking its client when it has one
+    let (default_transport, transport_name, with_client) = match options.client_transport {
+        ClientTransport::Reqwest => (
+            quote! { #connect_axum::transport::ReqwestTransport },
+            "reqwest",
+            Some(quote! {
+                pub fn with_client(
+                    base_url: impl Into<String>,
+                    client: #connect_axum::transport::reqwest::Client,
+                ) -> Self {
+                    Self::with_transport(base_url, client.into())
+                }
+            }),
+        ),
+        ClientTransport::Hyper => (
+            quote! { #connect_axum::transport::HyperTransport },
+            "hyper",
+            None,
+        ),
+    };
+
+    let client = options.client.then(|| {
+        quote! {
+            #[allow(unused)]
+            #[doc = concat!("Client for ", #service_name, ", over any `Transport` (", #transport_name, " by default)")]
+            #service_deprecated
+            #[derive(Clone)]
+            pub struct #client_ident<T = #default_transport> {
+                transport: T,
+                base_url: String,
+                encoding: #connect_axum::Encoding,
+                get_requests: bool,
+            }
+
+            #[allow(unused)]
+            #service_allow_deprecated
+            impl #client_ident {
+                pub fn new(base_url: impl Into<String>) -> Self {
+                    Self::with_transport(base_url, Default::default())
+                }
+
+                #with_client
+            }
+
+            #[allow(unused)]
+            #service_allow_deprecated
+            impl<T: #connect_axum::transport::Transport> #client_ident<T> {
+                pub fn with_transport(base_url: impl Into<String>, transport: T) -> Self {
+                    Self {
+                        transport,
+                        base_url: base_url.into(),
+                        encoding: #connect_axum::Encoding::proto(),
+                        get_requests: false,
+                    }
+                }
+
+                /// Send and receive messages in another encoding than Protobuf, like
+                /// `Encoding::json()`
+                pub fn with_encoding(mut self, encoding: #connect_axum::Encoding) -> Self {
+                    self.encoding = encoding;
+                    self
+                }
+
+                /// Call methods without side effects (marked with
+                /// `option idempotency_level = NO_SIDE_EFFECTS`) with `GET` requests, which HTTP
+                /// caches can store
+                pub fn with_get_requests(mut self, get_requests: bool) -> Self {
+                    self.get_requests = get_requests;
+                    self
+                }
+
+                #(#client_methods)*
+            }
+        }
+    });
+
+    quote! {
+        #service_trait
+
+        #client
+
+        #[allow(unused)]
+        #[doc = concat!("Metadata for the ", #service_name, " service")]
+        pub mod #meta_mod_name {
+            pub const SERVICE_NAME: &str = #service_path;
+
+            /// The encoded `FileDescriptorSet` of the service's file and its imports (empty
+            /// unless connect-axum's `reflect` feature is on)
+            pub const FILE_DESCRIPTOR_SET: &[u8] = #connect_axum::file_descriptor_set!(#descriptors);
+
+            pub struct MethodMeta {
+                pub name: &'static str,
+                pub path: &'static str,
+                pub idempotent: bool,
+                pub client_streaming: bool,
+                pub server_streaming: bool,
+            }
+
+            #(#method_metas)*
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::source_code_info::Location;
+    use prost_types::{
+        DescriptorProto, MethodOptions, ServiceDescriptorProto, ServiceOptions, SourceCodeInfo,
+    };
+
+    fn message(name: &str) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn method(name: &str) -> MethodDescriptorProto {
+        MethodDescriptorProto {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn service(name: &str, methods: Vec<MethodDescriptorProto>) -> ServiceDescriptorProto {
+        ServiceDescriptorProto {
+            name: Some(name.to_string()),
+            method: methods,
+            ..Default::default()
+        }
+    }
+
+    /// A file of `package` with `services`, and a `{Method}Request` and `{Method}Response`
+    /// message for each of their methods
+    fn file(
+        name: &str,
+        package: &str,
+        mut services: Vec<ServiceDescriptorProto>,
+    ) -> FileDescriptorProto {
+        let mut messages = Vec::new();
+
+        for method in services.iter_mut().flat_map(|service| &mut service.method) {
+            messages.push(message(&format!("{}Request", method.name())));
+            messages.push(message(&format!("{}Response", method.name())));
+
+            method.input_type = Some(format!(".{package}.{}Request", method.name()));
+            method.output_type = Some(format!(".{package}.{}Response", method.name()));
+        }
+
+        FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some(package.to_string()),
+            message_type: messages,
+            service: services,
+            ..Default::default()
+        }
+    }
+
+    /// The files generated for `files` with `parameter`, by name
+    fn generate(files: Vec<FileDescriptorProto>, parameter: &str) -> BTreeMap<String, String> {
+        let request = CodeGeneratorRequest {
+            file_to_generate: files.iter().map(|file| file.name().to_string()).collect(),
+            parameter: Some(parameter.to_string()),
+            proto_file: files,
+            compiler_version: None,
+        };
+
+        let response = super::generate(&request);
+        assert_eq!(response.error, None);
+
+        response
+            .file
+            .into_iter()
+            .map(|file| (file.name().to_string(), file.content().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn merges_the_services_of_a_package_into_one_file() {
+        let generated = generate(
+            vec![
+                file(
+                    "todos/v1/todos.proto",
+                    "todos.v1",
+                    vec![service("TodosService", vec![method("GetTodo")])],
+                ),
+                file(
+                    "todos/v1/admin.proto",
+                    "todos.v1",
+                    vec![service("AdminService", vec![method("Reset")])],
+                ),
+            ],
+            "",
+        );
+
+        assert_eq!(
+            generated.keys().collect::<Vec<_>>(),
+            ["todos.v1.connect.rs"]
+        );
+
+        let content = &generated["todos.v1.connect.rs"];
+        assert_eq!(content.matches("include!(\"todos.v1.rs\");").count(), 1);
+        assert!(content.contains("pub trait TodosService"));
+        assert!(content.contains("pub trait AdminService"));
+    }
+
+    #[test]
+    fn include_file_nests_a_module_per_package() {
+        let generated = generate(
+            vec![
+                file(
+                    "todos/v1/todos.proto",
+                    "todos.v1",
+                    vec![service("TodosService", vec![method("GetTodo")])],
+                ),
+                file(
+                    "common/v1/common.proto",
+                    "common.v1",
+                    vec![service("PingService", vec![method("Ping")])],
+                ),
+            ],
+            "include_file=mod.rs",
+        );
+
+        assert_eq!(
+            generated.keys().collect::<Vec<_>>(),
+            ["common.v1.connect.rs", "mod.rs", "todos.v1.connect.rs"]
+        );
+        assert!(generated["mod.rs"].ends_with(
+            "\
+pub mod common {
+    pub mod v1 {
+        include!(\"common.v1.connect.rs\");
+    }
+}
+pub mod todos {
+    pub mod v1 {
+        include!(\"todos.v1.connect.rs\");
+    }
+}
+"
+        ));
+    }
+
+    #[test]
+    fn descriptors_are_kept_only_for_reflection() {
+        let generated = generate(
+            vec![file(
+                "todos/v1/todos.proto",
+                "todos.v1",
+                vec![service("TodosService", vec![method("GetTodo")])],
+            )],
+            "",
+        );
+
+        // Through a macro of connect-axum, which drops them without its `reflect` feature
+        assert!(
+            generated["todos.v1.connect.rs"]
+                .contains("FILE_DESCRIPTOR_SET: &[u8] = connect_axum::file_descriptor_set!(")
+        );
+    }
+
+    #[test]
+    fn copies_comments_and_deprecation() {
+        let mut get_todo = method("GetTodo");
+        get_todo.options = Some(MethodOptions {
+            deprecated: Some(true),
+            ..Default::default()
+        });
+
+        let mut todos = service("TodosService", vec![get_todo, method("ListTodos")]);
+        todos.options = Some(ServiceOptions {
+            deprecated: Some(true),
+            ..Default::default()
+        });
+
+        let mut file = file("todos/v1/todos.proto", "todos.v1", vec![todos]);
+        let location = |path: Vec<i32>, leading: &str, trailing: &str| Location {
+            path,
+            leading_comments: Some(leading.to_string()),
+            trailing_comments: Some(trailing.to_string()),
+            ..Default::default()
+        };
+        file.source_code_info = Some(SourceCodeInfo {
+            location: vec![
+                location(vec![6, 0], " Keeps track of the things to do.\n", ""),
+                location(
+                    vec![6, 0, 2, 0],
+                    " Gets a single todo.\n",
+                    " Use ListTodos.\n",
+                ),
+            ],
+        });
+
+        let generated = generate(vec![file], "");
+        let content = &generated["todos.v1.connect.rs"];
+
+        assert!(content.contains(
+            "/// Keeps track of the things to do.\n#[deprecated]\npub trait TodosService"
+        ));
+        assert!(content.contains("#[deprecated]\n#[derive(Clone)]\npub struct TodosServiceClient"));
+
+        // On the trait's method and both of the client's, the trailing comment as a paragraph
+        let method_doc =
+            "    /// Gets a single todo.\n    ///\n    /// Use ListTodos.\n    #[deprecated]\n";
+        assert_eq!(content.matches(method_doc).count(), 3);
+
+        // And nowhere else, like on ListTodos
+        assert_eq!(content.matches("#[deprecated]").count(), 5);
+    }
+}
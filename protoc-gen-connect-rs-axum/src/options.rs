@@ -0,0 +1,178 @@
+//! The plugin's parameters, passed as `--connect-rs-axum_opt=key=value,...` to protoc or as
+//! `opt:` in `buf.gen.yaml`
+
+#[derive(Debug)]
+pub struct Options {
+    /// Generate the service traits (`server=false` for client-only crates)
+    pub server: bool,
+    /// Generate the clients (`client=false` for server-only crates)
+    pub client: bool,
+    /// The transport the clients use by default
+    pub client_transport: ClientTransport,
+    /// The suffix of the files prost generates for each package, included by ours
+    pub prost_file_suffix: String,
+    /// The name of a file to generate with a module for each package, including its code
+    pub include_file: Option<String>,
+    /// Protobuf paths (packages or types) of messages generated elsewhere, mapped to their
+    /// Rust paths, like prost-build's: `extern_path=.common.v1=::common::v1`
+    pub extern_paths: Vec<(String, String)>,
+    /// The path to connect-axum in generated code, for crates that re-export it
+    pub connect_axum_path: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientTransport {
+    Reqwest,
+    Hyper,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            server: true,
+            client: true,
+            client_transport: ClientTransport::Reqwest,
+            prost_file_suffix: ".rs".to_string(),
+            include_file: None,
+            extern_paths: Vec::new(),
+            connect_axum_path: "connect_axum".to_string(),
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(parameter: &str) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        for parameter in parameter
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let (key, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+            let value = value.trim();
+
+            match key.trim() {
+                "server" => options.server = parse_bool(key, value)?,
+                "client" => options.client = parse_bool(key, value)?,
+                "client_transport" => {
+                    options.client_transport = match value {
+                        "reqwest" => ClientTransport::Reqwest,
+                        "hyper" => ClientTransport::Hyper,
+                        _ => {
+                            return Err(format!(
+                                "Invalid client_transport: {value} (expected reqwest or hyper)"
+                            ));
+                        }
+                    }
+                }
+                "prost_file_suffix" => options.prost_file_suffix = value.to_string(),
+                "include_file" => {
+                    if value.is_empty() {
+                        return Err("Invalid include_file: expected a file name".to_string());
+                    }
+
+                    options.include_file = Some(value.to_string());
+                }
+                "extern_path" => {
+                    let (proto, rust) = value.split_once('=').ok_or_else(|| {
+                        format!("Invalid extern_path: {value} (expected .proto.path=::rust::path)")
+                    })?;
+
+                    // Fully-qualified, like protoc's type names
+                    let proto = format!(".{}", proto.trim().trim_start_matches('.'));
+
+                    options.extern_paths.push((proto, rust.trim().to_string()));
+                }
+                "connect_axum_path" => {
+                    if syn::parse_str::<syn::Path>(value).is_err() {
+                        return Err(format!(
+                            "Invalid connect_axum_path: {value} (expected a Rust path)"
+                        ));
+                    }
+
+                    options.connect_axum_path = value.to_string();
+                }
+                key => return Err(format!("Unknown parameter: {key}")),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// The path to connect-axum, to use in quoted code
+    pub fn connect_axum(&self) -> syn::Path {
+        // Checked when parsed
+        syn::parse_str(&self.connect_axum_path).expect("connect_axum_path is a path")
+    }
+}
+
+/// `true` or `false`, or `true` for a bare key (like `server`)
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "" | "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Invalid {key}: {value} (expected true or false)")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let options = Options::parse("").unwrap();
+        assert!(options.server);
+        assert!(options.client);
+        assert_eq!(options.client_transport, ClientTransport::Reqwest);
+        assert_eq!(options.prost_file_suffix, ".rs");
+        assert_eq!(options.include_file, None);
+        assert!(options.extern_paths.is_empty());
+        assert_eq!(options.connect_axum_path, "connect_axum");
+    }
+
+    #[test]
+    fn parses_every_parameter() {
+        let options = Options::parse(
+            "server=false, client, client_transport=hyper,prost_file_suffix=.prost.rs,\
+             include_file=mod.rs,connect_axum_path=::my::connect_axum",
+        )
+        .unwrap();
+
+        assert!(!options.server);
+        assert!(options.client);
+        assert_eq!(options.client_transport, ClientTransport::Hyper);
+        assert_eq!(options.prost_file_suffix, ".prost.rs");
+        assert_eq!(options.include_file.as_deref(), Some("mod.rs"));
+        assert_eq!(options.connect_axum_path, "::my::connect_axum");
+    }
+
+    #[test]
+    fn extern_paths_are_fully_qualified() {
+        let options =
+            Options::parse("extern_path=common.v1=::common::v1,extern_path=.a.B=crate::B").unwrap();
+
+        assert_eq!(
+            options.extern_paths,
+            [
+                (".common.v1".to_string(), "::common::v1".to_string()),
+                (".a.B".to_string(), "crate::B".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        for parameter in [
+            "server=yes",
+            "client_transport=curl",
+            "include_file=",
+            "extern_path=.common.v1",
+            "connect_axum_path=not a path",
+            "unknown=1",
+        ] {
+            assert!(Options::parse(parameter).is_err(), "{parameter}");
+        }
+    }
+}
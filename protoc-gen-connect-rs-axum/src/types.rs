@@ -0,0 +1,337 @@
+//! Resolving the fully-qualified Protobuf names of messages (like `.todos.v1.Todo`) to the Rust
+//! paths prost generates for them, relative to the module of the package being generated, just
+//! like prost-build does for the messages' fields.
+use std::collections::HashMap;
+
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use proc_macro2::{Ident, Span, TokenStream};
+use prost_types::{DescriptorProto, FileDescriptorProto};
+use quote::quote;
+
+use crate::options::Options;
+
+const WELL_KNOWN_TYPES: &str = ".google.protobuf";
+const EMPTY: &str = ".google.protobuf.Empty";
+const PROST_TYPES: &str = "::prost_types";
+
+/// Where prost puts a message: in its package's module, nested in the modules of the messages
+/// it's declared in
+struct MessagePath {
+    package: String,
+    parents: Vec<String>,
+    name: String,
+}
+
+pub struct TypeResolver {
+    messages: HashMap<String, MessagePath>,
+    /// Protobuf paths (packages or types) mapped to Rust paths, by `extern_path`
+    extern_paths: Vec<(String, String)>,
+}
+
+impl TypeResolver {
+    pub fn new(files: &[FileDescriptorProto], options: &Options) -> Self {
+        let mut messages = HashMap::new();
+
+        for file in files {
+            let package = file.package().to_string();
+
+            for message in &file.message_type {
+                index_message(&mut messages, &package, &[], message);
+            }
+        }
+
+        let mut extern_paths = options.extern_paths.clone();
+
+        // Mapping the well-known types elsewhere (to pbjson-types, say) replaces prost-types
+        let overrides_well_known_types = extern_paths
+            .iter()
+            .any(|(proto, _)| is_within(WELL_KNOWN_TYPES, proto));
+
+        if !overrides_well_known_types {
+            extern_paths.push((WELL_KNOWN_TYPES.to_string(), PROST_TYPES.to_string()));
+
+            // Unlike prost-types' `Empty`, connect-axum's has serde impls, so it's `{}` in JSON
+            // without canonical JSON
+            extern_paths.push((
+                EMPTY.to_string(),
+                format!("{}::Empty", options.connect_axum_path),
+            ));
+        }
+
+        Self {
+            messages,
+            extern_paths,
+        }
+    }
+
+    /// The Rust type of the message named `proto_type` (like `.common.v1.Page`), from within
+    /// the module of `package`
+    pub fn resolve(&self, proto_type: &str, package: &str) -> TokenStream {
+        if let Some(rust_type) = self.resolve_extern(proto_type) {
+            return rust_type;
+        }
+
+        let Some(message) = self.messages.get(proto_type) else {
+            // Not a message protoc told us about, so the best guess is the current package
+            let name = proto_type.rsplit('.').next().unwrap_or(proto_type);
+            let ident = ident(&name.to_upper_camel_case());
+            return quote! { #ident };
+        };
+
+        let mut path: Vec<TokenStream> = Vec::new();
+
+        if message.package != package {
+            let depth = package.split('.').filter(|s| !s.is_empty()).count();
+            path.extend((0..depth).map(|_| quote! { super }));
+            path.extend(
+                message
+                    .package
+                    .split('.')
+                    .filter(|s| !s.is_empty())
+                    .map(module),
+            );
+        }
+
+        path.extend(message.parents.iter().map(|parent| module(parent)));
+
+        let name = ident(&message.name.to_upper_camel_case());
+        path.push(quote! { #name });
+
+        quote! { #(#path)::* }
+    }
+
+    /// The Rust type from the longest `extern_path` matching `proto_type`, if any
+    fn resolve_extern(&self, proto_type: &str) -> Option<TokenStream> {
+        let (proto, rust) = self.extern_path(proto_type)?;
+
+        let mut rust_type = rust.clone();
+
+        // The rest of the name is made of packages or messages (modules), then the message
+        let rest: Vec<_> = proto_type[proto.len()..]
+            .split('.')
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        if let Some((name, modules)) = rest.split_last() {
+            for segment in modules {
+                rust_type.push_str("::");
+                rust_type.push_str(&escape(&segment.to_snake_case()));
+            }
+
+            rust_type.push_str("::");
+            rust_type.push_str(&escape(&name.to_upper_camel_case()));
+        }
+
+        syn::parse_str::<syn::Type>(&rust_type)
+            .ok()
+            .map(|rust_type| quote! { #rust_type })
+    }
+
+    /// Whether prost leaves the messages at `proto_path` (a package or type) to be generated
+    /// elsewhere: in prost-types for the well-known types, or wherever `extern_path` maps them
+    pub fn is_extern(&self, proto_path: &str) -> bool {
+        self.extern_path(proto_path).is_some()
+    }
+
+    /// The longest `extern_path` matching `proto_type`, if any
+    fn extern_path(&self, proto_type: &str) -> Option<&(String, String)> {
+        self.extern_paths
+            .iter()
+            .filter(|(proto, _)| is_within(proto_type, proto))
+            .max_by_key(|(proto, _)| proto.len())
+    }
+}
+
+/// Whether `proto_path` is `parent` or something in it (`.common.v1.Page` is within `.common`)
+fn is_within(proto_path: &str, parent: &str) -> bool {
+    proto_path == parent
+        || proto_path
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+fn index_message(
+    messages: &mut HashMap<String, MessagePath>,
+    package: &str,
+    parents: &[String],
+    message: &DescriptorProto,
+) {
+    let full_name = std::iter::once(package)
+        .chain(parents.iter().map(String::as_str))
+        .chain(std::iter::once(message.name()))
+        .filter(|s| !s.is_empty())
+        .fold(String::new(), |name, segment| format!("{name}.{segment}"));
+
+    let nested_parents: Vec<_> = parents
+        .iter()
+        .cloned()
+        .chain(std::iter::once(message.name().to_string()))
+        .collect();
+
+    for nested in &message.nested_type {
+        index_message(messages, package, &nested_parents, nested);
+    }
+
+    messages.insert(
+        full_name,
+        MessagePath {
+            package: package.to_string(),
+            parents: parents.to_vec(),
+            name: message.name().to_string(),
+        },
+    );
+}
+
+/// The module prost generates for a package segment or a message's nested types
+pub fn module(name: &str) -> TokenStream {
+    let ident = ident(&name.to_snake_case());
+    quote! { #ident }
+}
+
+/// An identifier, escaped like prost escapes it if it's a keyword
+fn ident(name: &str) -> Ident {
+    let name = escape(name);
+
+    match name.strip_prefix("r#") {
+        Some(raw) => Ident::new_raw(raw, Span::call_site()),
+        None => Ident::new(&name, Span::call_site()),
+    }
+}
+
+/// `r#type` for keywords, but `self_` for the keywords that can't be raw
+fn escape(name: &str) -> String {
+    match name {
+        "crate" | "self" | "super" | "Self" => format!("{name}_"),
+        _ if syn::parse_str::<Ident>(name).is_err() => format!("r#{name}"),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(name: &str, nested: Vec<DescriptorProto>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            nested_type: nested,
+            ..Default::default()
+        }
+    }
+
+    fn file(package: &str, messages: Vec<DescriptorProto>) -> FileDescriptorProto {
+        FileDescriptorProto {
+            package: Some(package.to_string()),
+            message_type: messages,
+            ..Default::default()
+        }
+    }
+
+    fn resolver(parameter: &str) -> TypeResolver {
+        let files = [
+            file(
+                "common.v1",
+                vec![message("Page", vec![message("Cursor", vec![])])],
+            ),
+            file("todos.v1", vec![message("Todo", vec![])]),
+            file("", vec![message("Root", vec![])]),
+        ];
+
+        TypeResolver::new(&files, &Options::parse(parameter).unwrap())
+    }
+
+    fn resolve(resolver: &TypeResolver, proto_type: &str, package: &str) -> String {
+        resolver
+            .resolve(proto_type, package)
+            .to_string()
+            .replace(' ', "")
+    }
+
+    #[test]
+    fn resolves_messages_of_the_same_package() {
+        let types = resolver("");
+        assert_eq!(resolve(&types, ".todos.v1.Todo", "todos.v1"), "Todo");
+        assert_eq!(
+            resolve(&types, ".common.v1.Page.Cursor", "common.v1"),
+            "page::Cursor"
+        );
+    }
+
+    #[test]
+    fn resolves_messages_of_other_packages_through_super() {
+        let types = resolver("");
+        assert_eq!(
+            resolve(&types, ".common.v1.Page", "todos.v1"),
+            "super::super::common::v1::Page"
+        );
+        assert_eq!(
+            resolve(&types, ".common.v1.Page.Cursor", "todos.v1"),
+            "super::super::common::v1::page::Cursor"
+        );
+        assert_eq!(resolve(&types, ".Root", "todos.v1"), "super::super::Root");
+        assert_eq!(resolve(&types, ".todos.v1.Todo", ""), "todos::v1::Todo");
+    }
+
+    #[test]
+    fn resolves_extern_paths() {
+        let types =
+            resolver("extern_path=.common=::common,extern_path=common.v1.Page=::paging::Page");
+
+        // The longest match wins
+        assert_eq!(
+            resolve(&types, ".common.v1.Page", "todos.v1"),
+            "::paging::Page"
+        );
+        assert_eq!(
+            resolve(&types, ".common.v1.Page.Cursor", "todos.v1"),
+            "::paging::Page::Cursor"
+        );
+        assert_eq!(
+            resolve(&types, ".common.v2.Sort", "todos.v1"),
+            "::common::v2::Sort"
+        );
+    }
+
+    #[test]
+    fn resolves_empty_to_connect_axum() {
+        let types = resolver("connect_axum_path=::my::connect_axum");
+        assert_eq!(
+            resolve(&types, ".google.protobuf.Empty", "todos.v1"),
+            "::my::connect_axum::Empty"
+        );
+
+        let types = resolver("extern_path=.google.protobuf=::pbjson_types");
+        assert_eq!(
+            resolve(&types, ".google.protobuf.Empty", "todos.v1"),
+            "::pbjson_types::Empty"
+        );
+    }
+
+    #[test]
+    fn resolves_other_well_known_types_to_prost_types() {
+        let types = resolver("");
+        assert_eq!(
+            resolve(&types, ".google.protobuf.Timestamp", "todos.v1"),
+            "::prost_types::Timestamp"
+        );
+
+        let types = resolver("extern_path=.google.protobuf.Timestamp=::my::Timestamp");
+        assert_eq!(
+            resolve(&types, ".google.protobuf.Timestamp", "todos.v1"),
+            "::my::Timestamp"
+        );
+        assert_eq!(
+            resolve(&types, ".google.protobuf.Duration", "todos.v1"),
+            "::prost_types::Duration"
+        );
+    }
+
+    #[test]
+    fn extern_packages() {
+        let types = resolver("extern_path=.common=::common");
+        assert!(types.is_extern(".common.v1"));
+        assert!(types.is_extern(".google.protobuf"));
+        assert!(!types.is_extern(".todos.v1"));
+        assert!(!types.is_extern(".commonplace"));
+    }
+}